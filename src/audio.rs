@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use std::{fs::File, path::Path};
+use symphonia::core::{
+    codecs::*, formats::FormatReader, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+/// Opens `path` with whatever demuxer matches its contents.
+pub fn open(path: &Path) -> Result<Box<dyn FormatReader>> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &Default::default(),
+        &MetadataOptions::default(),
+    )?;
+    Ok(probed.format)
+}
+
+/// What [`properties`] could find out about a file.
+pub struct Properties {
+    /// Length in seconds.
+    pub duration: Option<f64>,
+    /// Short codec name, e.g. `flac`, `mp3` or `pcm`.
+    pub codec: Option<String>,
+}
+
+const CODECS: &[(CodecType, &str)] = &[
+    (CODEC_TYPE_FLAC, "flac"),
+    (CODEC_TYPE_ALAC, "alac"),
+    (CODEC_TYPE_WAVPACK, "wavpack"),
+    (CODEC_TYPE_MONKEYS_AUDIO, "ape"),
+    (CODEC_TYPE_TTA, "tta"),
+    (CODEC_TYPE_MP3, "mp3"),
+    (CODEC_TYPE_AAC, "aac"),
+    (CODEC_TYPE_VORBIS, "vorbis"),
+    (CODEC_TYPE_OPUS, "opus"),
+    (CODEC_TYPE_WMA, "wma"),
+    (CODEC_TYPE_MUSEPACK, "musepack"),
+];
+
+/// Track length and codec. The length is estimated from the bitrate for MP3s without
+/// a VBR header.
+pub fn properties(path: &Path) -> Result<Properties> {
+    let format = open(path)?;
+    let track = format.default_track().context("no audio track")?;
+    let params = &track.codec_params;
+    let duration = params.n_frames.and_then(|frames| match params.time_base {
+        Some(base) => {
+            let time = base.calc_time(frames);
+            Some(time.seconds as f64 + time.frac)
+        }
+        None => Some(frames as f64 / params.sample_rate? as f64),
+    });
+    let codec = CODECS
+        .iter()
+        .find(|(codec, _)| *codec == params.codec)
+        .map(|(_, name)| name.to_string())
+        .or_else(|| {
+            let name = symphonia::default::get_codecs()
+                .get_codec(params.codec)?
+                .short_name;
+            Some(match name.starts_with("pcm") {
+                true => "pcm".to_string(),
+                false => name.to_string(),
+            })
+        });
+    Ok(Properties { duration, codec })
+}
@@ -0,0 +1,619 @@
+use crate::{fingerprint::Fingerprint, release::ReleaseType, song_data::Song, unique::Marker};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// Bumped whenever [`Entry`] changes, older caches are thrown away.
+const VERSION: u32 = 10;
+
+/// A cache whose file has this many times more lines than entries (by appending changed
+/// entries again and again) is written again from scratch on the next save.
+const MAX_GROWTH: usize = 2;
+
+/// The first line of the cache file. Every other line is a [`Line`], later lines replace
+/// earlier ones for the same path, so saving only has to append the entries that changed.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    /// Which fingerprint file belongs to the cache, see [`fingerprints_path`].
+    generation: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Line<P = PathBuf, E = Entry> {
+    path: P,
+    #[serde(flatten)]
+    entry: E,
+}
+
+/// Where the cache lives unless `--cache` says otherwise.
+pub fn default_path() -> Option<PathBuf> {
+    Some(
+        dirs::cache_dir()?
+            .join("subset-album")
+            .join("scan-cache.json"),
+    )
+}
+
+/// Everything the tag pass found out about a file, valid as long as the size and
+/// modification time of the file stay the same.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct Entry {
+    size: u64,
+    modified: SystemTime,
+    name: String,
+    unique: bool,
+    /// The `--unique-marker` `unique` was read with.
+    unique_marker: String,
+    artist: Option<String>,
+    album_artist: Option<String>,
+    album: Option<String>,
+    track: Option<u16>,
+    total_tracks: Option<u16>,
+    disc: Option<u16>,
+    total_discs: Option<u16>,
+    release_type: Option<ReleaseType>,
+    duration: Option<f64>,
+    codec: Option<String>,
+    bitrate: Option<u32>,
+    fingerprint: Option<Stored>,
+}
+
+/// Where a fingerprint is stored in the fingerprint file, as little endian `u32`s. They
+/// would make up most of the cache as JSON, and are only read for the songs that need them.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct Stored {
+    offset: u64,
+    len: u32,
+}
+
+pub struct Cache {
+    path: Option<PathBuf>,
+    state: Mutex<State>,
+    /// Held while this process may write the cache, `None` if another one (e.g. a headless
+    /// run next to the GUI) does, as its rewrites delete the fingerprints this one uses.
+    lock: Option<File>,
+}
+
+struct State {
+    entries: HashMap<PathBuf, Entry>,
+    /// Paths inserted since the last save.
+    changed: HashSet<PathBuf>,
+    /// Lines in the cache file after the header.
+    lines: usize,
+    generation: u64,
+    /// The fingerprint file of [`State::generation`], opened once it is needed.
+    fingerprints: Option<File>,
+    /// Whether the next save has to write the whole cache, because it is new or a line
+    /// couldn't be read.
+    rewrite: bool,
+    /// Whether entries of files that no longer exist were dropped already.
+    pruned: bool,
+}
+impl Cache {
+    /// Loads the cache at `path`, starting over if it is missing or unreadable.
+    /// Without a path nothing is cached.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut state = State {
+            entries: HashMap::new(),
+            changed: HashSet::new(),
+            lines: 0,
+            generation: 0,
+            fingerprints: None,
+            rewrite: true,
+            pruned: false,
+        };
+        if let Some(file) = path.as_ref().and_then(|p| File::open(p).ok()) {
+            let mut lines = BufReader::new(file).lines();
+            let header = lines
+                .next()
+                .and_then(|l| serde_json::from_str::<Header>(&l.ok()?).ok())
+                .filter(|h| h.version == VERSION);
+            if let Some(header) = header {
+                state.generation = header.generation;
+                state.rewrite = false;
+                for line in lines {
+                    state.lines += 1;
+                    match line
+                        .ok()
+                        .and_then(|l| serde_json::from_str::<Line>(&l).ok())
+                    {
+                        Some(line) => _ = state.entries.insert(line.path, line.entry),
+                        // e.g. half a line written before a crash
+                        None => state.rewrite = true,
+                    }
+                }
+            }
+        }
+        let lock = path.as_deref().and_then(lock);
+        Self {
+            path,
+            state: Mutex::new(state),
+            lock,
+        }
+    }
+
+    /// The cached song at `path` if the file hasn't changed since it was cached.
+    pub fn get(&self, path: &Path, needs_fingerprint: bool, marker: &Marker) -> Option<Song> {
+        let cache = self.path.as_ref()?;
+        let (size, modified) = stat(path)?;
+        let mut state = self.state.lock().unwrap();
+        let entry = state.entries.get(path)?;
+        if entry.size != size
+            || entry.modified != modified
+            || (needs_fingerprint && entry.fingerprint.is_none())
+            || entry.unique_marker != marker.to_string()
+        {
+            return None;
+        }
+        let fingerprint = match (needs_fingerprint, entry.fingerprint) {
+            (true, Some(stored)) => Some(state.read_fingerprint(cache, stored).ok()?),
+            _ => None,
+        };
+        let entry = state.entries.get(path)?;
+        Some(Song {
+            name: entry.name.clone(),
+            title_inferred: false,
+            path: path.to_path_buf(),
+            unique: entry.unique,
+            artist: entry.artist.clone(),
+            album_artist: entry.album_artist.clone(),
+            album: entry.album.clone(),
+            track: entry.track,
+            total_tracks: entry.total_tracks,
+            disc: entry.disc,
+            total_discs: entry.total_discs,
+            release_type: entry.release_type,
+            duration: entry.duration,
+            codec: entry.codec.clone(),
+            bitrate: entry.bitrate,
+            fingerprint,
+        })
+    }
+
+    pub fn insert(&self, song: &Song, marker: &Marker) {
+        let (Some(cache), Some(_)) = (&self.path, &self.lock) else {
+            return;
+        };
+        let Some((size, modified)) = stat(&song.path) else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        // an unchanged fingerprint isn't stored again
+        let old = state.entries.get(&song.path).and_then(|e| e.fingerprint);
+        let fingerprint = match &song.fingerprint {
+            Some(fingerprint) => match old.filter(|&stored| {
+                state
+                    .read_fingerprint(cache, stored)
+                    .is_ok_and(|old| old == *fingerprint)
+            }) {
+                Some(stored) => Some(stored),
+                None => match state.write_fingerprint(cache, fingerprint) {
+                    Ok(stored) => Some(stored),
+                    Err(e) => {
+                        eprintln!("Error caching fingerprint: {e}");
+                        None
+                    }
+                },
+            },
+            None => None,
+        };
+        let entry = Entry {
+            size,
+            modified,
+            name: song.name.clone(),
+            unique: song.unique,
+            unique_marker: marker.to_string(),
+            artist: song.artist.clone(),
+            album_artist: song.album_artist.clone(),
+            album: song.album.clone(),
+            track: song.track,
+            total_tracks: song.total_tracks,
+            disc: song.disc,
+            total_discs: song.total_discs,
+            release_type: song.release_type,
+            duration: song.duration,
+            codec: song.codec.clone(),
+            bitrate: song.bitrate,
+            fingerprint,
+        };
+        // songs that can't be fingerprinted are read again every time
+        if state.entries.get(&song.path) != Some(&entry) {
+            state.entries.insert(song.path.clone(), entry);
+            state.changed.insert(song.path.clone());
+        }
+    }
+
+    /// Appends the entries that changed since the last save to the cache file. The first
+    /// save drops the entries of files that no longer exist, writing the whole cache again
+    /// if there were any.
+    pub fn save(&self) -> Result<()> {
+        let (Some(path), Some(_)) = (&self.path, &self.lock) else {
+            return Ok(());
+        };
+        let mut state = self.state.lock().unwrap();
+        if !std::mem::replace(&mut state.pruned, true) {
+            let before = state.entries.len();
+            state.entries.retain(|path, _| path.exists());
+            state.rewrite |= state.entries.len() < before;
+        }
+        if state.rewrite || state.lines > MAX_GROWTH * state.entries.len() {
+            return state.write(path);
+        }
+        if state.changed.is_empty() {
+            return Ok(());
+        }
+        let mut file = BufWriter::new(OpenOptions::new().append(true).open(path)?);
+        for changed in std::mem::take(&mut state.changed) {
+            if let Some(entry) = state.entries.get(&changed) {
+                let line = Line {
+                    path: &changed,
+                    entry,
+                };
+                serde_json::to_writer(&mut file, &line)?;
+                writeln!(file)?;
+                state.lines += 1;
+            }
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
+impl State {
+    /// Writes the whole cache, with a new fingerprint file holding only the fingerprints
+    /// of its entries.
+    fn write(&mut self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let generation = self.generation + 1;
+        // the fingerprints are copied over from the current file
+        let _ = self.open_fingerprints(path);
+        let mut old = self.fingerprints.take();
+        let mut fingerprints = BufWriter::new(File::create(fingerprints_path(path, generation))?);
+        let mut offset = 0;
+        let mut moved = HashMap::new();
+        // write next to the cache and rename so a crash never leaves half a cache behind
+        let tmp = path.with_extension("json.tmp");
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(
+            &mut file,
+            &Header {
+                version: VERSION,
+                generation,
+            },
+        )?;
+        writeln!(file)?;
+        for (song, entry) in &self.entries {
+            let stored = entry
+                .fingerprint
+                .and_then(|stored| read_fingerprint(old.as_mut()?, stored).ok());
+            let stored = match stored {
+                Some(fingerprint) => {
+                    fingerprints.write_all(&to_bytes(&fingerprint))?;
+                    let stored = Stored {
+                        offset,
+                        len: fingerprint.len() as u32,
+                    };
+                    offset += fingerprint.len() as u64 * 4;
+                    Some(stored)
+                }
+                None => None,
+            };
+            let line = Line {
+                path: song,
+                entry: Entry {
+                    fingerprint: stored,
+                    ..entry.clone()
+                },
+            };
+            serde_json::to_writer(&mut file, &line)?;
+            writeln!(file)?;
+            moved.insert(song.clone(), stored);
+        }
+        fingerprints.flush()?;
+        file.flush()?;
+        fs::rename(tmp, path)?;
+
+        remove_old_fingerprints(path, generation);
+        for (song, stored) in moved {
+            if let Some(entry) = self.entries.get_mut(&song) {
+                entry.fingerprint = stored;
+            }
+        }
+        self.generation = generation;
+        self.fingerprints = None;
+        self.lines = self.entries.len();
+        self.changed.clear();
+        self.rewrite = false;
+        Ok(())
+    }
+
+    fn open_fingerprints(&mut self, path: &Path) -> io::Result<&mut File> {
+        if self.fingerprints.is_none() {
+            let file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(fingerprints_path(path, self.generation))?;
+            self.fingerprints = Some(file);
+        }
+        Ok(self.fingerprints.as_mut().unwrap())
+    }
+
+    fn read_fingerprint(&mut self, path: &Path, stored: Stored) -> io::Result<Fingerprint> {
+        read_fingerprint(self.open_fingerprints(path)?, stored)
+    }
+
+    fn write_fingerprint(&mut self, path: &Path, fingerprint: &[u32]) -> io::Result<Stored> {
+        let file = self.open_fingerprints(path)?;
+        let offset = file.seek(SeekFrom::End(0))?;
+        file.write_all(&to_bytes(fingerprint))?;
+        Ok(Stored {
+            offset,
+            len: fingerprint.len() as u32,
+        })
+    }
+}
+
+/// The file the fingerprints of the cache at `path` are stored in.
+fn fingerprints_path(path: &Path, generation: u64) -> PathBuf {
+    path.with_extension(format!("fingerprints.{generation}"))
+}
+
+/// Deletes the fingerprint files of the cache at `path` other than the one of
+/// `generation`, including ones left behind by an older version or a failed save.
+fn remove_old_fingerprints(path: &Path, generation: u64) {
+    let current = fingerprints_path(path, generation);
+    let Some(prefix) = current.file_stem() else {
+        return;
+    };
+    let prefix = format!("{}.", prefix.to_string_lossy());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let name = entry.file_name();
+        let old = name.to_str().is_some_and(|name| {
+            name.strip_prefix(&prefix)
+                .is_some_and(|n| n.parse::<u64>().is_ok())
+        });
+        if old && entry.path() != current {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Locks the cache at `path` for this process, `None` if another process has it.
+fn lock(path: &Path) -> Option<File> {
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let result = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path.with_extension("lock"))
+        .and_then(|file| {
+            file.try_lock()?;
+            Ok(file)
+        });
+    match result {
+        Ok(file) => Some(file),
+        Err(e) => {
+            eprintln!("Cache {path:?} is in use ({e}), reading it without updating it");
+            None
+        }
+    }
+}
+
+fn read_fingerprint(file: &mut File, stored: Stored) -> io::Result<Fingerprint> {
+    let mut bytes = vec![0; stored.len as usize * 4];
+    file.seek(SeekFrom::Start(stored.offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+fn to_bytes(fingerprint: &[u32]) -> Vec<u8> {
+    fingerprint.iter().flat_map(|n| n.to_le_bytes()).collect()
+}
+
+fn stat(path: &Path) -> Option<(u64, SystemTime)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty folder for the cache and songs of one test.
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("subset-album-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn song(dir: &Path, name: &str, fingerprint: Option<&[u32]>) -> Song {
+        let path = dir.join(format!("{name}.mp3"));
+        fs::write(&path, name).unwrap();
+        let mut song = Song::new(path);
+        song.name = name.to_string();
+        song.fingerprint = fingerprint.map(Fingerprint::from);
+        song
+    }
+
+    fn cached(cache: &Cache, song: &Song) -> Option<(String, Option<Fingerprint>)> {
+        let cached = cache.get(&song.path, song.fingerprint.is_some(), &Marker::default())?;
+        Some((cached.name, cached.fingerprint))
+    }
+
+    fn lines(path: &Path) -> usize {
+        fs::read_to_string(path).unwrap().lines().count()
+    }
+
+    fn header(path: &Path) -> Header {
+        let file = fs::read_to_string(path).unwrap();
+        serde_json::from_str(file.lines().next().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn save_appends_changes() {
+        let dir = dir("append");
+        let path = dir.join("cache.json");
+        let one = song(&dir, "one", Some(&[1, 2, 3]));
+        let mut two = song(&dir, "two", None);
+        let cache = Cache::load(Some(path.clone()));
+        cache.insert(&one, &Marker::default());
+        cache.insert(&two, &Marker::default());
+        cache.save().unwrap();
+        assert_eq!(lines(&path), 3);
+        drop(cache);
+
+        let cache = Cache::load(Some(path.clone()));
+        assert_eq!(
+            cached(&cache, &one),
+            Some(("one".to_string(), one.fingerprint.clone()))
+        );
+        assert_eq!(cached(&cache, &two), Some(("two".to_string(), None)));
+        // unchanged songs aren't written again, changed ones are appended
+        cache.insert(&one, &Marker::default());
+        two.name = "Two".to_string();
+        cache.insert(&two, &Marker::default());
+        cache.save().unwrap();
+        assert_eq!(lines(&path), 4);
+        assert_eq!(header(&path).generation, 1);
+        drop(cache);
+
+        let cache = Cache::load(Some(path.clone()));
+        assert_eq!(cached(&cache, &two), Some(("Two".to_string(), None)));
+        // a changed file isn't taken from the cache
+        fs::write(&one.path, "changed").unwrap();
+        assert_eq!(cached(&cache, &one), None);
+    }
+
+    #[test]
+    fn rewrite_keeps_fingerprints() {
+        let dir = dir("rewrite");
+        let path = dir.join("cache.json");
+        let one = song(&dir, "one", Some(&[1, 2, 3]));
+        let two = song(&dir, "two", Some(&[4, 5]));
+        let cache = Cache::load(Some(path.clone()));
+        cache.insert(&one, &Marker::default());
+        cache.insert(&two, &Marker::default());
+        cache.save().unwrap();
+        drop(cache);
+        // left behind by an older version
+        fs::write(dir.join("cache.fingerprints.7"), "").unwrap();
+
+        // the removed song is dropped, which writes everything again
+        fs::remove_file(&one.path).unwrap();
+        let cache = Cache::load(Some(path.clone()));
+        cache.save().unwrap();
+        drop(cache);
+        assert_eq!(lines(&path), 2);
+        assert_eq!(header(&path).generation, 2);
+        let mut files = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "cache.fingerprints.2",
+                "cache.json",
+                "cache.lock",
+                "two.mp3"
+            ]
+        );
+        let cache = Cache::load(Some(path.clone()));
+        assert_eq!(
+            cached(&cache, &two),
+            Some(("two".to_string(), two.fingerprint.clone()))
+        );
+    }
+
+    #[test]
+    fn rewrite_after_growing() {
+        let dir = dir("growth");
+        let path = dir.join("cache.json");
+        let mut one = song(&dir, "one", Some(&[1]));
+        let cache = Cache::load(Some(path.clone()));
+        for (name, lines_after) in [("a", 1), ("b", 2), ("c", 3), ("d", 1)] {
+            one.name = name.to_string();
+            cache.insert(&one, &Marker::default());
+            cache.save().unwrap();
+            assert_eq!(lines(&path), lines_after + 1, "{name}");
+        }
+        drop(cache);
+        let cache = Cache::load(Some(path.clone()));
+        assert_eq!(
+            cached(&cache, &one),
+            Some(("d".to_string(), one.fingerprint.clone()))
+        );
+    }
+
+    #[test]
+    fn truncated_line_rewrites() {
+        let dir = dir("truncated");
+        let path = dir.join("cache.json");
+        let one = song(&dir, "one", None);
+        let cache = Cache::load(Some(path.clone()));
+        cache.insert(&one, &Marker::default());
+        cache.save().unwrap();
+        drop(cache);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"path\":\"/half").unwrap();
+
+        let cache = Cache::load(Some(path.clone()));
+        assert_eq!(cached(&cache, &one), Some(("one".to_string(), None)));
+        cache.save().unwrap();
+        assert_eq!(lines(&path), 2);
+        assert_eq!(header(&path).generation, 2);
+    }
+
+    #[test]
+    fn other_version_is_discarded() {
+        let dir = dir("version");
+        let path = dir.join("cache.json");
+        let one = song(&dir, "one", None);
+        let cache = Cache::load(Some(path.clone()));
+        cache.insert(&one, &Marker::default());
+        cache.save().unwrap();
+        drop(cache);
+        let file = fs::read_to_string(&path).unwrap();
+        let old = file.replacen(&format!("\"version\":{VERSION}"), "\"version\":1", 1);
+        fs::write(&path, old).unwrap();
+
+        let cache = Cache::load(Some(path.clone()));
+        assert_eq!(cached(&cache, &one), None);
+    }
+
+    #[test]
+    fn one_writer() {
+        let dir = dir("lock");
+        let path = dir.join("cache.json");
+        let one = song(&dir, "one", None);
+        let writer = Cache::load(Some(path.clone()));
+        let reader = Cache::load(Some(path.clone()));
+        reader.insert(&one, &Marker::default());
+        reader.save().unwrap();
+        assert!(!path.exists());
+        writer.insert(&one, &Marker::default());
+        writer.save().unwrap();
+        assert_eq!(lines(&path), 2);
+    }
+}
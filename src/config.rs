@@ -0,0 +1,284 @@
+use crate::{
+    cache, filename,
+    group::Layout,
+    pattern::Pattern,
+    quality::{self, Quality},
+    release::{ReleaseType, SubsetRule},
+    unique::Marker,
+};
+use anyhow::{bail, Context, Result};
+use std::{env::args, path::PathBuf, str::FromStr};
+
+pub const USAGE: &str = "\
+usage: subset-album [options] <path-to-collection>...
+
+options:
+  --headless, report    print the analysis to stdout instead of opening the GUI
+  --export <file>       write all findings to a .json or .csv file, implies --headless
+  --partial <n|n%>      report albums sharing at least n songs (or n% of their songs)
+                        with another album as partial subsets
+  --exact               compare titles byte for byte instead of normalizing them
+  --strip <word>        also ignore bracketed or ` - ` separated title suffixes containing
+                        the word <word> (defaults: remaster, live, edit, feat, version, ...)
+  --max-distance <n>    treat normalized titles at most n edits apart as the same
+  --duration-tolerance <seconds>
+                        songs whose lengths differ by more than this never match (default 2)
+  --ignore-duration     match songs regardless of their length
+  --fingerprint         decode the audio and match songs by their content instead of their
+                        titles, this also finds duplicates among songs without titles
+  --cache <file>        where to cache tags between runs (default: ~/.cache/subset-album)
+  --no-cache            read the tags of every file again instead of using the cache
+  --group-by <folders|tags>
+                        group songs into artists and albums by the artist/album folders
+                        (default) or by their album artist and album tags
+  --layout <pattern>    group songs by the folders a pattern like
+                        \"{label}/{artist}/{year} - {album}/{disc}\" describes
+  --title-pattern <pattern>
+                        where to find the title of songs without a title tag in their
+                        filename, e.g. \"{track} - {title}\"; can be given several times
+                        and replaces the defaults
+  --no-title-fallback   leave songs without a title tag untitled
+  --quality <list>      which copy of an album found in several formats to keep, best first
+                        (default: flac,alac,wavpack,ape,pcm,mp3:320,aac:256,mp3:245,opus,...)
+  --ignore-subset <type:type>
+                        don't report albums of the first type that are subsets of albums of
+                        the second, e.g. single:album; types are album, single, ep, live,
+                        compilation or * for any
+  --only-subset <type:type>
+                        only report subsets matching one of these rules, e.g. album:album
+  --unique-marker <comment:text|field:name>
+                        which tag marks a song as unique so it never matches other songs,
+                        the comment being exactly text or a custom TXXX/Vorbis/MP4 field being
+                        set (default: comment:unique); .subset-ignore files also mark songs
+  --duplicates          also find songs whose audio is byte for byte identical, ignoring tags
+  --cross-artist        also find albums whose songs all appear on an album of another
+                        artist, matching songs by their track artist tag
+  --watch               rescan artists automatically when their files change
+  --ignore-list <file>  where dismissed findings are stored
+                        (default: ~/.local/share/subset-album/ignored.json)
+  --quarantine <dir>    where \"Quick Remove\" moves albums to when quarantining them
+                        (default: ~/.local/share/subset-album/quarantine)
+  -h, --help            show this message";
+
+/// Title suffixes ignored when normalizing, see `Matcher::normalize`.
+const SUFFIXES: &[&str] = &[
+    "remaster",
+    "live",
+    "edit",
+    "feat",
+    "ft",
+    "featuring",
+    "version",
+    "mix",
+    "mono",
+    "stereo",
+    "bonus track",
+    "demo",
+    "acoustic",
+    "explicit",
+    "clean",
+];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub roots: Vec<PathBuf>,
+    pub group_by: GroupBy,
+    /// Only set when grouping by [`GroupBy::Layout`].
+    pub layout: Option<Layout>,
+    pub headless: bool,
+    pub exports: Vec<PathBuf>,
+    /// Minimum overlap for `Info::PartialSubset`, disabled when `None`.
+    pub partial: Option<Threshold>,
+    pub normalize: bool,
+    pub suffixes: Vec<String>,
+    pub max_distance: usize,
+    /// Maximum length difference in seconds for two songs to match, `None` to ignore lengths.
+    pub duration_tolerance: Option<f64>,
+    pub fingerprint: bool,
+    /// Codecs and bitrates from best to worst, see `quality::preference`.
+    pub quality: Vec<Quality>,
+    /// Tried on the filenames of songs without a title tag, see `filename::infer`.
+    pub title_patterns: Vec<Pattern>,
+    pub ignore_subsets: Vec<SubsetRule>,
+    /// Subsets are only reported if they match one of these, unless it's empty.
+    pub only_subsets: Vec<SubsetRule>,
+    pub unique: Marker,
+    pub cross_artist: bool,
+    pub duplicates: bool,
+    /// File the scan cache is stored in, nothing is cached when `None`.
+    pub cache: Option<PathBuf>,
+    pub watch: bool,
+    pub quarantine: Option<PathBuf>,
+    /// File dismissed findings are stored in, see `ignore::IgnoreList`.
+    pub ignore_list: Option<PathBuf>,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            group_by: GroupBy::Folders,
+            layout: None,
+            headless: false,
+            exports: Vec::new(),
+            partial: None,
+            normalize: true,
+            suffixes: SUFFIXES.iter().map(|s| s.to_string()).collect(),
+            max_distance: 0,
+            duration_tolerance: Some(2.0),
+            fingerprint: false,
+            quality: quality::preference(quality::DEFAULT).unwrap(),
+            title_patterns: filename::DEFAULT
+                .iter()
+                .map(|p| p.parse().unwrap())
+                .collect(),
+            ignore_subsets: Vec::new(),
+            only_subsets: Vec::new(),
+            unique: Marker::default(),
+            cross_artist: false,
+            duplicates: false,
+            cache: cache::default_path(),
+            watch: false,
+            quarantine: dirs::data_dir().map(|d| d.join("subset-album").join("quarantine")),
+            ignore_list: dirs::data_dir().map(|d| d.join("subset-album").join("ignored.json")),
+        }
+    }
+}
+impl Config {
+    pub fn from_args() -> Result<Self> {
+        let mut config = Config::default();
+        let mut args = args().skip(1);
+        let mut default_titles = true;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" | "report" => config.headless = true,
+                "--export" => {
+                    config.exports.push(value(&mut args, &arg)?.into());
+                    config.headless = true;
+                }
+                "--partial" => config.partial = Some(value(&mut args, &arg)?.parse()?),
+                "--exact" => config.normalize = false,
+                "--strip" => config.suffixes.push(value(&mut args, &arg)?),
+                "--max-distance" => {
+                    config.max_distance = value(&mut args, &arg)?
+                        .parse()
+                        .context("invalid edit distance")?
+                }
+                "--duration-tolerance" => {
+                    config.duration_tolerance = Some(
+                        value(&mut args, &arg)?
+                            .parse()
+                            .context("invalid duration tolerance")?,
+                    )
+                }
+                "--ignore-duration" => config.duration_tolerance = None,
+                "--fingerprint" => config.fingerprint = true,
+                "--cache" => config.cache = Some(value(&mut args, &arg)?.into()),
+                "--no-cache" => config.cache = None,
+                "--group-by" => config.group_by = value(&mut args, &arg)?.parse()?,
+                "--layout" => {
+                    config.layout = Some(value(&mut args, &arg)?.parse()?);
+                    config.group_by = GroupBy::Layout;
+                }
+                "--title-pattern" => {
+                    let pattern = value(&mut args, &arg)?.parse()?;
+                    if std::mem::take(&mut default_titles) {
+                        config.title_patterns.clear();
+                    }
+                    config.title_patterns.push(pattern);
+                }
+                "--no-title-fallback" => config.title_patterns.clear(),
+                "--quality" => config.quality = quality::preference(&value(&mut args, &arg)?)?,
+                "--ignore-subset" => config.ignore_subsets.push(value(&mut args, &arg)?.parse()?),
+                "--only-subset" => config.only_subsets.push(value(&mut args, &arg)?.parse()?),
+                "--unique-marker" => config.unique = value(&mut args, &arg)?.parse()?,
+                "--cross-artist" => config.cross_artist = true,
+                "--duplicates" => config.duplicates = true,
+                "--watch" => config.watch = true,
+                "--ignore-list" => config.ignore_list = Some(value(&mut args, &arg)?.into()),
+                "--quarantine" => config.quarantine = Some(value(&mut args, &arg)?.into()),
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ if arg.starts_with('-') => bail!("unknown option {arg:?}\n\n{USAGE}"),
+                _ => config.roots.push(PathBuf::from(arg)),
+            }
+        }
+        if config.roots.is_empty() {
+            bail!("missing path to collection\n\n{USAGE}")
+        }
+        for root in &config.roots {
+            if !root.is_dir() {
+                bail!("{root:?} is not a directory");
+            }
+        }
+        Ok(config)
+    }
+
+    /// Whether an album of type `subset` being a (partial) subset of one of type `of`
+    /// should be reported according to `--ignore-subset` and `--only-subset`.
+    pub fn reports_subset(&self, subset: ReleaseType, of: ReleaseType) -> bool {
+        (self.only_subsets.is_empty() || self.only_subsets.iter().any(|r| r.matches(subset, of)))
+            && !self.ignore_subsets.iter().any(|r| r.matches(subset, of))
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    match args.next() {
+        Some(v) => Ok(v),
+        None => bail!("{flag} expects a value\n\n{USAGE}"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// `<root>/<artist>/<album>/`
+    Folders,
+    /// The album artist and album tags, wherever the songs are.
+    Tags,
+    /// The folders `--layout` describes.
+    Layout,
+}
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "folders" => GroupBy::Folders,
+            "tags" => GroupBy::Tags,
+            _ => bail!("unknown grouping {s:?}, expected folders or tags"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Threshold {
+    Count(usize),
+    Percent(f32),
+}
+impl Threshold {
+    /// Whether `overlaps` shared songs out of an album of `total` songs is enough.
+    pub fn reached(&self, overlaps: usize, total: usize) -> bool {
+        match *self {
+            Threshold::Count(n) => overlaps >= n.max(1),
+            Threshold::Percent(p) => overlaps > 0 && percent(overlaps, total) >= p,
+        }
+    }
+}
+impl FromStr for Threshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.strip_suffix('%') {
+            Some(p) => Threshold::Percent(p.parse().context("invalid percentage")?),
+            None => Threshold::Count(s.parse().context("invalid song count")?),
+        })
+    }
+}
+
+pub fn percent(part: usize, total: usize) -> f32 {
+    match total {
+        0 => 0.0,
+        _ => part as f32 / total as f32 * 100.0,
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::{bail, Result};
+use std::io::{Read, Seek, SeekFrom};
+
+/// A chunk of a RIFF file, its id and where its body starts and how long it says it is.
+pub struct Chunk {
+    pub id: [u8; 4],
+    pub start: u64,
+    pub size: u64,
+}
+
+/// The chunks between `start` and `end` of a RIFF file, or of the body of one of its
+/// `LIST` chunks.
+pub fn riff_chunks(file: &mut (impl Read + Seek), start: u64, end: u64) -> Result<Vec<Chunk>> {
+    let mut chunks = Vec::new();
+    let mut offset = start;
+    while offset + 8 <= end {
+        let mut header = [0; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let size = u32::from_le_bytes(header[4..8].try_into()?) as u64;
+        chunks.push(Chunk {
+            id: header[..4].try_into()?,
+            start: offset + 8,
+            size,
+        });
+        // chunks are padded to an even size
+        offset += 8 + size + size % 2;
+    }
+    Ok(chunks)
+}
+
+/// The end of a file of `len` bytes without the ID3v1 tag at its end, if it has one.
+pub fn before_id3v1(file: &mut (impl Read + Seek), len: u64) -> Result<u64> {
+    if len < 128 {
+        return Ok(len);
+    }
+    let mut tag = [0; 3];
+    file.seek(SeekFrom::Start(len - 128))?;
+    file.read_exact(&mut tag)?;
+    Ok(match &tag {
+        b"TAG" => len - 128,
+        _ => len,
+    })
+}
+
+/// An APEv2 tag, found by its footer.
+pub struct ApeTag {
+    /// Where the tag starts, including its header if it has one.
+    pub start: u64,
+    /// Where its items start and end, the footer follows them.
+    pub items: (u64, u64),
+    pub count: u32,
+}
+
+/// The APEv2 tag ending at `end`, `None` if there is none.
+pub fn ape_tag(file: &mut (impl Read + Seek), end: u64) -> Result<Option<ApeTag>> {
+    if end < 32 {
+        return Ok(None);
+    }
+    let mut footer = [0; 32];
+    file.seek(SeekFrom::Start(end - 32))?;
+    file.read_exact(&mut footer)?;
+    if !footer.starts_with(b"APETAGEX") {
+        return Ok(None);
+    }
+    // the size counts the items and the footer, but not the header
+    let size = u32::from_le_bytes(footer[12..16].try_into()?) as u64;
+    let count = u32::from_le_bytes(footer[16..20].try_into()?);
+    let header = match footer[23] & 0x80 {
+        0 => 0,
+        _ => 32,
+    };
+    if size < 32 || size + header > end {
+        bail!("invalid APE tag");
+    }
+    Ok(Some(ApeTag {
+        start: end - size - header,
+        items: (end - size, end - 32),
+        count,
+    }))
+}
+
+/// Reads the header of the Ogg page `file` is at and returns its lacing values, one per
+/// segment of its body which follows.
+pub fn ogg_page(file: &mut impl Read) -> Result<Vec<u8>> {
+    let mut header = [0; 27];
+    file.read_exact(&mut header)?;
+    if !header.starts_with(b"OggS") {
+        bail!("invalid Ogg page");
+    }
+    let mut lacing = vec![0; header[26] as usize];
+    file.read_exact(&mut lacing)?;
+    Ok(lacing)
+}
+
+/// Whether a segment of `len` bytes ends a packet, packets go on as long as segments are
+/// full.
+pub fn ends_packet(len: u8) -> bool {
+    len < 255
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A RIFF chunk, padded to an even size.
+    pub fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((body.len() as u32).to_le_bytes());
+        chunk.extend(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    /// An APEv2 tag without a header holding `(key, value, flags)` items.
+    pub fn ape(items: &[(&str, &str, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (key, value, flags) in items {
+            data.extend((value.len() as u32).to_le_bytes());
+            data.extend(flags.to_le_bytes());
+            data.extend(key.as_bytes());
+            data.push(0);
+            data.extend(value.as_bytes());
+        }
+        let size = data.len() as u32 + 32;
+        data.extend(b"APETAGEX");
+        for n in [2000, size, items.len() as u32, 0] {
+            data.extend(n.to_le_bytes());
+        }
+        data.extend([0; 8]);
+        data
+    }
+
+    /// An Ogg page whose body is `segments`, which are at most 255 bytes each.
+    pub fn page(segments: &[&[u8]]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.extend([0; 22]);
+        page.push(segments.len() as u8);
+        page.extend(segments.iter().map(|s| s.len() as u8));
+        for segment in segments {
+            page.extend(*segment);
+        }
+        page
+    }
+
+    #[test]
+    fn padded_chunks() {
+        let mut file = chunk(b"fmt ", b"odd");
+        file.extend(chunk(b"data", b"even"));
+        let len = file.len() as u64;
+        let chunks = riff_chunks(&mut Cursor::new(file), 0, len).unwrap();
+        let chunks = chunks
+            .iter()
+            .map(|c| (&c.id, c.start, c.size))
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, vec![(b"fmt ", 8, 3), (b"data", 20, 4)]);
+    }
+
+    #[test]
+    fn ape_tag_before_id3v1() {
+        let mut file = b"audio".to_vec();
+        file.extend(ape(&[("Title", "One", 0)]));
+        file.extend(b"TAG");
+        file.extend([0; 125]);
+        let mut file = Cursor::new(file);
+        let end = before_id3v1(&mut file, 5 + 49 + 128).unwrap();
+        assert_eq!(end, 5 + 49);
+        let tag = ape_tag(&mut file, end).unwrap().unwrap();
+        assert_eq!((tag.start, tag.items, tag.count), (5, (5, 22), 1));
+        assert!(ape_tag(&mut file, 5).unwrap().is_none());
+    }
+
+    #[test]
+    fn ogg_pages() {
+        let mut stream = page(&[&[1; 255], b"end"]);
+        stream.extend(b"OggX");
+        let mut stream = Cursor::new(stream);
+        let lacing = ogg_page(&mut stream).unwrap();
+        assert_eq!(lacing, vec![255, 3]);
+        assert_eq!(
+            lacing.iter().map(|l| ends_packet(*l)).collect::<Vec<_>>(),
+            vec![false, true]
+        );
+        stream.set_position(stream.position() + 258);
+        assert!(ogg_page(&mut stream).is_err());
+    }
+}
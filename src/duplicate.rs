@@ -0,0 +1,330 @@
+use crate::container;
+use anyhow::{bail, Result};
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Hashes of audio payloads, kept between global passes so unchanged files aren't read again.
+#[derive(Default)]
+pub struct Hashes(HashMap<PathBuf, (u64, SystemTime, blake3::Hash)>);
+impl Hashes {
+    /// Groups of files in `paths` whose audio is byte for byte identical. Only files with the
+    /// same payload size are hashed.
+    pub fn identical(&mut self, paths: &[&Path]) -> Vec<Vec<PathBuf>> {
+        let mut sizes = HashMap::<u64, Vec<(&Path, u64, SystemTime, Vec<Range>)>>::new();
+        for path in paths {
+            let Ok(meta) = fs::metadata(path) else {
+                continue;
+            };
+            let Ok(modified) = meta.modified() else {
+                continue;
+            };
+            let Ok(ranges) = payload(path, meta.len()) else {
+                continue;
+            };
+            sizes
+                .entry(ranges.iter().map(|(start, end)| end - start).sum())
+                .or_default()
+                .push((path, meta.len(), modified, ranges));
+        }
+
+        let candidates = sizes
+            .into_values()
+            .filter(|files| files.len() > 1)
+            .flatten()
+            .collect::<Vec<_>>();
+        let hashes = candidates
+            .par_iter()
+            .filter_map(|(path, size, modified, ranges)| {
+                let hash = match self.0.get(*path) {
+                    Some(&(s, m, hash)) if s == *size && m == *modified => hash,
+                    _ => hash(path, ranges).ok()?,
+                };
+                Some((*path, *size, *modified, hash))
+            })
+            .collect::<Vec<_>>();
+
+        let mut groups = HashMap::<blake3::Hash, Vec<PathBuf>>::new();
+        for (path, size, modified, hash) in hashes {
+            self.0.insert(path.to_path_buf(), (size, modified, hash));
+            groups.entry(hash).or_default().push(path.to_path_buf());
+        }
+        let mut groups = groups
+            .into_values()
+            .filter(|paths| paths.len() > 1)
+            .map(|mut paths| {
+                paths.sort();
+                paths
+            })
+            .collect::<Vec<_>>();
+        groups.sort();
+        groups
+    }
+}
+
+/// A byte range of a file, from its start to its end.
+type Range = (u64, u64);
+
+fn hash(path: &Path, ranges: &[Range]) -> Result<blake3::Hash> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    for &(start, end) in ranges {
+        file.seek(SeekFrom::Start(start))?;
+        io::copy(&mut (&mut file).take(end - start), &mut hasher)?;
+    }
+    Ok(hasher.finalize())
+}
+
+/// The byte ranges of `path` holding its audio, without its ID3v2, FLAC metadata, APEv2 and
+/// ID3v1 tags, MP4 boxes other than `mdat`, Ogg header packets and WAV chunks other than
+/// `data`, so retagged copies of a file still hash the same.
+fn payload(path: &Path, len: u64) -> Result<Vec<Range>> {
+    audio(&mut File::open(path)?, len)
+}
+
+/// The byte ranges of the audio of a file of `len` bytes, see [`payload`]. Files without
+/// any audio, like ones holding only tags, are an error so they don't all hash the same.
+fn audio(file: &mut (impl Read + Seek), len: u64) -> Result<Vec<Range>> {
+    let ranges = audio_ranges(file, len)?;
+    if ranges.iter().any(|&(start, end)| start > end || end > len) {
+        bail!("invalid audio range");
+    }
+    if ranges.iter().all(|(start, end)| start == end) {
+        bail!("no audio");
+    }
+    Ok(ranges)
+}
+
+fn audio_ranges(file: &mut (impl Read + Seek), len: u64) -> Result<Vec<Range>> {
+    let mut header = Vec::new();
+    file.take(12).read_to_end(&mut header)?;
+    if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WAVE") {
+        return riff_data(file, len);
+    } else if header.get(4..8) == Some(b"ftyp") {
+        return mp4_mdat(file, len);
+    } else if header.starts_with(b"OggS") {
+        return ogg_audio(file, len);
+    }
+
+    let mut start = 0;
+    if header.starts_with(b"ID3") && header.len() >= 10 {
+        let size = header[6..10]
+            .iter()
+            .fold(0, |size, b| (size << 7) | (*b as u64 & 0x7f));
+        let footer = match header[5] & 0x10 {
+            0 => 0,
+            _ => 10,
+        };
+        start = 10 + size + footer;
+    } else if header.starts_with(b"fLaC") {
+        start = 4;
+        loop {
+            let mut block = [0; 4];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut block)?;
+            start += 4 + u32::from_be_bytes([0, block[1], block[2], block[3]]) as u64;
+            if block[0] & 0x80 != 0 {
+                break;
+            }
+        }
+    }
+
+    let mut end = container::before_id3v1(file, len)?;
+    if let Some(tag) = container::ape_tag(file, end)? {
+        end = tag.start;
+    }
+    Ok(vec![(start, end)])
+}
+
+/// The `data` chunk of a WAV file.
+fn riff_data(file: &mut (impl Read + Seek), len: u64) -> Result<Vec<Range>> {
+    let chunks = container::riff_chunks(file, 12, len)?;
+    match chunks.iter().find(|chunk| &chunk.id == b"data") {
+        Some(data) => Ok(vec![(data.start, (data.start + data.size).min(len))]),
+        None => bail!("no data chunk"),
+    }
+}
+
+/// The contents of the `mdat` boxes of an MP4 file, the tags live in `moov`.
+fn mp4_mdat(file: &mut (impl Read + Seek), len: u64) -> Result<Vec<Range>> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= len {
+        let mut header = [0; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let (size, header_len) = match u32::from_be_bytes(header[..4].try_into()?) {
+            // the box goes on until the end of the file
+            0 => (len - offset, 8),
+            1 => {
+                let mut size = [0; 8];
+                file.read_exact(&mut size)?;
+                (u64::from_be_bytes(size), 16)
+            }
+            size => (size as u64, 8),
+        };
+        if size < header_len {
+            bail!("invalid MP4 box");
+        }
+        if &header[4..] == b"mdat" {
+            ranges.push((offset + header_len, (offset + size).min(len)));
+        }
+        offset += size;
+    }
+    if ranges.is_empty() {
+        bail!("no mdat box");
+    }
+    Ok(ranges)
+}
+
+/// The bodies of the pages of an Ogg Vorbis or Opus file after its header packets, which
+/// hold the comments.
+fn ogg_audio(file: &mut (impl Read + Seek), len: u64) -> Result<Vec<Range>> {
+    let mut ranges = Vec::new();
+    let mut headers = None;
+    let mut packets = 0;
+    let mut offset = 0;
+    while offset + 27 <= len {
+        file.seek(SeekFrom::Start(offset))?;
+        let lacing = container::ogg_page(file)?;
+        let body = offset + 27 + lacing.len() as u64;
+        let size = lacing.iter().map(|l| *l as u64).sum::<u64>();
+        let headers = match headers {
+            Some(headers) => headers,
+            None => {
+                // the first packet says which codec it is, and so how many headers it has
+                let mut id = [0; 8];
+                file.read_exact(&mut id)?;
+                let count = if id.starts_with(b"\x01vorbis") {
+                    3
+                } else if id.starts_with(b"OpusHead") {
+                    2
+                } else {
+                    return Ok(vec![(0, len)]);
+                };
+                *headers.insert(count)
+            }
+        };
+        // audio always starts on a new page
+        if packets >= headers {
+            ranges.push((body, (body + size).min(len)));
+        }
+        packets += lacing
+            .iter()
+            .filter(|l| container::ends_packet(**l))
+            .count();
+        offset = body + size;
+    }
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::tests::{ape, chunk, page};
+    use std::io::Cursor;
+
+    /// The audio of `file`, as it is hashed.
+    fn audio_of(file: &[u8]) -> Vec<u8> {
+        audio(&mut Cursor::new(file), file.len() as u64)
+            .unwrap()
+            .iter()
+            .flat_map(|&(start, end)| file[start as usize..end as usize].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn id3_and_ape_tags() {
+        let mut file = b"ID3\x04\x00\x00\x00\x00\x00\x05tags!".to_vec();
+        file.extend(b"\xff\xfbaudio");
+        file.extend(ape(&[("Title", "Song", 0)]));
+        file.extend(b"TAG");
+        file.extend([0; 125]);
+        assert_eq!(audio_of(&file), b"\xff\xfbaudio");
+    }
+
+    #[test]
+    fn no_audio() {
+        let tag_only = b"ID3\x04\x00\x00\x00\x00\x00\x04tags";
+        assert!(audio(&mut Cursor::new(tag_only), tag_only.len() as u64).is_err());
+        let truncated = b"ID3\x04\x00\x00\x00\x01\x7fshort";
+        assert!(audio(&mut Cursor::new(truncated), truncated.len() as u64).is_err());
+        let mut ogg = page(&[b"\x01vorbis id"]);
+        ogg.extend(page(&[b"\x03vorbis comments", b"\x05vorbis setup"]));
+        assert!(audio(&mut Cursor::new(&ogg), ogg.len() as u64).is_err());
+    }
+
+    #[test]
+    fn flac_metadata() {
+        let mut file = b"fLaC".to_vec();
+        file.extend([0, 0, 0, 2, 1, 2]);
+        file.extend([0x84, 0, 0, 3, b'a', b'b', b'c']);
+        file.extend(b"frames");
+        assert_eq!(audio_of(&file), b"frames");
+    }
+
+    #[test]
+    fn wav_data_chunk() {
+        let wav = |info: &[u8]| {
+            let mut file = b"RIFF\0\0\0\0WAVE".to_vec();
+            file.extend(chunk(b"LIST", info));
+            file.extend(chunk(b"fmt ", b"odd"));
+            file.extend(chunk(b"data", b"samples"));
+            file
+        };
+        assert_eq!(audio_of(&wav(b"INFOINAM")), b"samples");
+        assert_eq!(audio_of(&wav(b"INFOINAM\x01")), b"samples");
+        assert!(audio(&mut Cursor::new(b"RIFF\0\0\0\0WAVE"), 12).is_err());
+    }
+
+    fn mp4_box(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut b = (body.len() as u32 + 8).to_be_bytes().to_vec();
+        b.extend(kind);
+        b.extend(body);
+        b
+    }
+
+    #[test]
+    fn mp4_mdat_boxes() {
+        let mut file = mp4_box(b"ftyp", b"M4A ");
+        file.extend(mp4_box(b"mdat", b"one"));
+        file.extend(mp4_box(b"moov", b"tags"));
+        // a box with a 64 bit size
+        file.extend(1u32.to_be_bytes());
+        file.extend(b"mdat");
+        file.extend(19u64.to_be_bytes());
+        file.extend(b"two");
+        // a box going on until the end of the file
+        file.extend(0u32.to_be_bytes());
+        file.extend(b"mdatthree");
+        assert_eq!(audio_of(&file), b"onetwothree");
+
+        let mut invalid = mp4_box(b"ftyp", b"M4A ");
+        invalid.extend(4u32.to_be_bytes());
+        invalid.extend(b"mdat");
+        assert!(audio(&mut Cursor::new(&invalid), invalid.len() as u64).is_err());
+    }
+
+    #[test]
+    fn ogg_pages_after_the_headers() {
+        let mut vorbis = page(&[b"\x01vorbis id"]);
+        vorbis.extend(page(&[b"\x03vorbis comments", b"\x05vorbis setup"]));
+        vorbis.extend(page(&[b"one", b"two"]));
+        vorbis.extend(page(&[b"three"]));
+        assert_eq!(audio_of(&vorbis), b"onetwothree");
+
+        let mut opus = page(&[b"OpusHead"]);
+        opus.extend(page(&[b"OpusTags"]));
+        opus.extend(page(&[b"audio"]));
+        assert_eq!(audio_of(&opus), b"audio");
+
+        // other codecs are hashed whole
+        let flac = page(&[b"\x7fFLAC id"]);
+        assert_eq!(audio_of(&flac), flac);
+    }
+}
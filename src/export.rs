@@ -0,0 +1,159 @@
+use crate::{matching::MatchKind, messages::*, InfoTree};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// One flattened finding, the shape both the JSON and CSV exports use.
+#[derive(Serialize)]
+pub struct Finding<'a> {
+    pub artist: &'a str,
+    pub album: &'a str,
+    pub kind: &'static str,
+    pub other_album: Option<&'a str>,
+    pub other_artist: Option<&'a str>,
+    pub titles: Vec<&'a str>,
+    /// Titles among the overlapping songs that were parsed from their filenames.
+    pub inferred_titles: Vec<&'a str>,
+    pub paths: Vec<&'a Path>,
+    /// Where each of `titles` was found.
+    pub other_paths: Vec<&'a Path>,
+    pub matched_via: Vec<MatchKind>,
+}
+
+/// CSV can't hold nested lists, so the list columns are joined with `; `.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    artist: &'a str,
+    album: &'a str,
+    kind: &'static str,
+    other_album: &'a str,
+    other_artist: &'a str,
+    titles: String,
+    inferred_titles: String,
+    paths: String,
+    other_paths: String,
+    matched_via: String,
+}
+
+pub fn findings(info: &InfoTree) -> Vec<Finding<'_>> {
+    let mut findings = Vec::new();
+    for (artist, albums) in info {
+        for (album, fields) in albums {
+            for field in fields {
+                let songs = field.overlapping_songs();
+                let mut paths = songs
+                    .iter()
+                    .map(|o| o.song.path.as_path())
+                    .collect::<Vec<_>>();
+                let mut other_paths = songs
+                    .iter()
+                    .map(|o| o.other.path.as_path())
+                    .collect::<Vec<_>>();
+                let mut titles = songs
+                    .iter()
+                    .map(|o| o.song.name.as_str())
+                    .collect::<Vec<_>>();
+                match field {
+                    Info::IdenticalFile(path, others) => {
+                        paths.push(path);
+                        other_paths.extend(others.iter().map(PathBuf::as_path));
+                    }
+                    Info::DuplicateTrack(_, duplicates) => {
+                        paths.extend(duplicates.iter().map(PathBuf::as_path))
+                    }
+                    Info::Covers(albums) => titles.extend(albums.iter().map(String::as_str)),
+                    Info::Incomplete(problems) => {
+                        titles.extend(problems.iter().map(String::as_str))
+                    }
+                    Info::Empty(path) => paths.push(path),
+                    Info::MissingTitle(missing) => paths.extend(missing.iter().map(Path::new)),
+                    Info::InferredTitle(inferred) => {
+                        for (path, title) in inferred {
+                            paths.push(Path::new(path));
+                            titles.push(title);
+                        }
+                    }
+                    Info::PartialSubset(..)
+                    | Info::Subset(..)
+                    | Info::CrossSubset(..)
+                    | Info::OtherFormat(..) => {}
+                }
+                findings.push(Finding {
+                    artist,
+                    album,
+                    kind: field.kind(),
+                    other_album: field.other_album(),
+                    other_artist: field.other_artist(),
+                    titles,
+                    inferred_titles: match field {
+                        Info::InferredTitle(inferred) => {
+                            inferred.iter().map(|(_, t)| t.as_str()).collect()
+                        }
+                        _ => songs
+                            .iter()
+                            .flat_map(|o| [&o.song, &o.other])
+                            .filter(|s| s.title_inferred)
+                            .map(|s| s.name.as_str())
+                            .collect(),
+                    },
+                    paths,
+                    other_paths,
+                    matched_via: songs.iter().map(|o| o.via).collect(),
+                });
+            }
+        }
+    }
+    findings
+}
+
+pub fn write_json(path: &Path, info: &InfoTree) -> Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(file, &findings(info))?;
+    Ok(())
+}
+
+pub fn write_csv(path: &Path, info: &InfoTree) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for finding in findings(info) {
+        writer.serialize(CsvRow {
+            artist: finding.artist,
+            album: finding.album,
+            kind: finding.kind,
+            other_album: finding.other_album.unwrap_or_default(),
+            other_artist: finding.other_artist.unwrap_or_default(),
+            titles: finding.titles.join("; "),
+            inferred_titles: finding.inferred_titles.join("; "),
+            paths: join_paths(&finding.paths),
+            other_paths: join_paths(&finding.other_paths),
+            matched_via: finding
+                .matched_via
+                .iter()
+                .map(|v| format!("{v:?}"))
+                .collect::<Vec<_>>()
+                .join("; "),
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes `info` as JSON or CSV depending on the extension of `path`.
+pub fn write(path: &Path, info: &InfoTree) -> Result<()> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => write_json(path, info),
+        Some("csv") => write_csv(path, info),
+        _ => bail!("don't know how to export to {path:?}, use a .json or .csv file"),
+    }
+}
+
+fn join_paths(paths: &[&Path]) -> String {
+    paths
+        .iter()
+        .map(|p| p.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,138 @@
+use crate::{pattern::Pattern, song_data::*};
+use anyhow::{bail, Result};
+use std::{collections::HashMap, path::Path};
+
+/// Tried in order on the filenames of songs without a title unless `--title-pattern` says
+/// otherwise. `{track}` only matches numbers and `{artist}` only the song's artist.
+pub const DEFAULT: &[&str] = &[
+    "{track} - {artist} - {title}",
+    "{track} - {title}",
+    "{track}. {title}",
+    "{track} {title}",
+    "{artist} - {title}",
+    "{title}",
+];
+
+/// The fields `pattern` finds in the filename of `path`, without its extension.
+pub fn fields(pattern: &Pattern, path: &Path) -> Result<HashMap<String, String>> {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    match pattern.captures(&name) {
+        Some(fields) => Ok(fields),
+        None => bail!("{name:?} doesn't match the pattern"),
+    }
+}
+
+/// Gives a song without a title tag the title (and track number) of the first pattern
+/// its filename fits.
+pub fn infer(song: &mut Song, patterns: &[Pattern]) {
+    if song.name != MISSING {
+        return;
+    }
+    for pattern in patterns {
+        let Ok(mut fields) = fields(pattern, &song.path) else {
+            continue;
+        };
+        let track = match fields.get("track") {
+            Some(track) if track.chars().all(|c| c.is_ascii_digit()) => track.parse().ok(),
+            Some(_) => continue,
+            None => None,
+        };
+        if let Some(artist) = fields.get("artist") {
+            if !is_artist(song, artist) {
+                continue;
+            }
+        }
+        let Some(title) = fields.remove("title").filter(|t| !t.is_empty()) else {
+            continue;
+        };
+        song.name = title;
+        song.title_inferred = true;
+        song.track = song.track.or(track);
+        return;
+    }
+}
+
+/// Whether `artist` is the artist tag of `song` or the name of one of its folders, so a
+/// title like `Song - Live` isn't taken for an artist prefix.
+fn is_artist(song: &Song, artist: &str) -> bool {
+    let same = |name: &str| name.eq_ignore_ascii_case(artist);
+    song.artist.as_deref().is_some_and(same)
+        || song.album_artist.as_deref().is_some_and(same)
+        || song
+            .path
+            .ancestors()
+            .skip(1)
+            .filter_map(|dir| dir.file_name())
+            .any(|name| same(&name.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn infer_title(path: &str, artist: Option<&str>) -> (String, Option<u16>) {
+        let patterns = DEFAULT
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect::<Vec<_>>();
+        let mut song = Song::new(PathBuf::from(path));
+        song.artist = artist.map(str::to_string);
+        infer(&mut song, &patterns);
+        (song.name, song.track)
+    }
+
+    #[test]
+    fn default_patterns() {
+        let title = |name: &str, track| (name.to_string(), track);
+        assert_eq!(
+            infer_title("/m/Band/Album/01 - Band - Song.mp3", None),
+            title("Song", Some(1))
+        );
+        assert_eq!(
+            infer_title("/m/Band/Album/02 - Song.mp3", None),
+            title("Song", Some(2))
+        );
+        assert_eq!(
+            infer_title("/m/Band/Album/03. Song.mp3", None),
+            title("Song", Some(3))
+        );
+        assert_eq!(
+            infer_title("/m/Band/Album/04 Song.mp3", None),
+            title("Song", Some(4))
+        );
+        assert_eq!(
+            infer_title("/m/x/Album/Singer - Song.mp3", Some("singer")),
+            title("Song", None)
+        );
+        assert_eq!(
+            infer_title("/m/Band/Album/Song.mp3", None),
+            title("Song", None)
+        );
+    }
+
+    #[test]
+    fn track_and_artist_must_fit() {
+        // `Intro` isn't a track number and `Song` isn't the artist
+        assert_eq!(
+            infer_title("/m/Band/Album/Intro - Outro.mp3", None),
+            ("Intro - Outro".to_string(), None)
+        );
+        assert_eq!(
+            infer_title("/m/Band/Album/Song - Live.mp3", None),
+            ("Song - Live".to_string(), None)
+        );
+        assert_eq!(
+            infer_title("/m/Band/Album/Band - Live.mp3", None),
+            ("Live".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn tagged_titles_are_kept() {
+        let mut song = Song::new(PathBuf::from("/m/01 - Other.mp3"));
+        song.name = "Title".to_string();
+        infer(&mut song, &["{track} - {title}".parse().unwrap()]);
+        assert_eq!((song.name.as_str(), song.title_inferred), ("Title", false));
+    }
+}
@@ -0,0 +1,266 @@
+//! Content based song fingerprints in the spirit of Chromaprint/Haitsma-Kalker:
+//! the audio is decoded, downmixed and downsampled, split into overlapping frames,
+//! and every frame becomes 32 bits describing how the energy of neighbouring
+//! frequency bands changes over time. Two recordings of the same audio end up with
+//! mostly identical bits even across codecs and bitrates.
+
+use crate::audio;
+use anyhow::{bail, Context, Result};
+use std::{f32::consts::PI, path::Path, sync::Arc};
+use symphonia::core::{audio::SampleBuffer, errors::Error};
+
+const RATE: u32 = 5512;
+const MAX_SECONDS: usize = 120;
+const FRAME: usize = 2048;
+const HOP: usize = 512;
+const BANDS: usize = 33;
+const MIN_FREQ: f32 = 300.0;
+const MAX_FREQ: f32 = 2000.0;
+/// How many frames two fingerprints may be shifted against each other.
+const MAX_OFFSET: isize = 8;
+/// Fingerprints need at least this many overlapping frames to be compared.
+const MIN_FRAMES: usize = 16;
+/// Fraction of differing bits below which two fingerprints are the same recording.
+const MAX_BIT_ERROR: f32 = 0.3;
+
+pub type Fingerprint = Arc<[u32]>;
+
+pub fn compute(path: &Path) -> Result<Fingerprint> {
+    let mut format = audio::open(path)?;
+    let track = format.default_track().context("no audio track")?;
+    let track_id = track.id;
+    let rate = track
+        .codec_params
+        .sample_rate
+        .context("unknown sample rate")?;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &Default::default())?;
+
+    let mut samples = Vec::new();
+    let (mut acc, mut count, mut pos) = (0.0, 0, 0);
+    let mut buf: Option<SampleBuffer<f32>> = None;
+    while samples.len() < RATE as usize * MAX_SECONDS {
+        let packet = match format.next_packet() {
+            Ok(p) => p,
+            Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(d) => d,
+            Err(Error::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        if buf
+            .as_ref()
+            .is_none_or(|b| b.capacity() < decoded.capacity())
+        {
+            buf = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+        }
+        let buf = buf.as_mut().unwrap();
+        buf.copy_interleaved_ref(decoded);
+
+        // downmix to mono and downsample by averaging
+        for frame in buf.samples().chunks(channels) {
+            acc += frame.iter().sum::<f32>() / channels as f32;
+            count += 1;
+            pos += RATE;
+            if pos >= rate {
+                pos -= rate;
+                samples.push(acc / count as f32);
+                (acc, count) = (0.0, 0);
+            }
+        }
+    }
+    let hashes = hashes(&samples);
+    if hashes.len() < MIN_FRAMES {
+        bail!("too short to fingerprint");
+    }
+    Ok(hashes.into())
+}
+
+fn hashes(samples: &[f32]) -> Vec<u32> {
+    let window = (0..FRAME)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME as f32).cos())
+        .collect::<Vec<_>>();
+    let edges = (0..=BANDS)
+        .map(|b| {
+            let freq = MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(b as f32 / BANDS as f32);
+            (freq * FRAME as f32 / RATE as f32) as usize
+        })
+        .collect::<Vec<_>>();
+
+    let mut previous: Option<[f32; BANDS]> = None;
+    let mut hashes = Vec::new();
+    let mut buf = vec![(0.0, 0.0); FRAME];
+    for start in (0..samples.len().saturating_sub(FRAME)).step_by(HOP) {
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = (samples[start + i] * window[i], 0.0);
+        }
+        fft(&mut buf);
+        let mut energy = [0.0; BANDS];
+        for (b, e) in energy.iter_mut().enumerate() {
+            *e = buf[edges[b]..edges[b + 1].max(edges[b] + 1)]
+                .iter()
+                .map(|(re, im)| re * re + im * im)
+                .sum();
+        }
+        if let Some(previous) = previous {
+            let mut hash = 0;
+            for b in 0..BANDS - 1 {
+                let now = energy[b] - energy[b + 1];
+                let before = previous[b] - previous[b + 1];
+                if now - before > 0.0 {
+                    hash |= 1 << b;
+                }
+            }
+            hashes.push(hash);
+        }
+        previous = Some(energy);
+    }
+    hashes
+}
+
+/// In place iterative radix-2 FFT, `buf.len()` must be a power of two.
+fn fft(buf: &mut [(f32, f32)]) {
+    let n = buf.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (re, im) = buf[start + k + len / 2];
+                let t = (re * cos - im * sin, re * sin + im * cos);
+                let u = buf[start + k];
+                buf[start + k] = (u.0 + t.0, u.1 + t.1);
+                buf[start + k + len / 2] = (u.0 - t.0, u.1 - t.1);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Whether two fingerprints describe the same recording, allowing for a small
+/// offset between them (e.g. differing encoder delays).
+pub fn similar(a: &[u32], b: &[u32]) -> bool {
+    (-MAX_OFFSET..=MAX_OFFSET).any(|offset| {
+        let (a, b) = match offset < 0 {
+            true => (a, b.get(offset.unsigned_abs()..).unwrap_or_default()),
+            false => (a.get(offset as usize..).unwrap_or_default(), b),
+        };
+        let frames = a.len().min(b.len());
+        if frames < MIN_FRAMES {
+            return false;
+        }
+        let errors = a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum::<u32>();
+        (errors as f32 / (frames * 32) as f32) < MAX_BIT_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pseudo random numbers, the same for the same seed.
+    fn random(seed: u32) -> impl Iterator<Item = u32> {
+        std::iter::successors(Some(seed), |n| {
+            Some(n.wrapping_mul(1_664_525).wrapping_add(1_013_904_223))
+        })
+        .skip(1)
+    }
+
+    /// Ten seconds of quarter second tones between `MIN_FREQ` and `MAX_FREQ`.
+    fn melody(seed: u32) -> Vec<f32> {
+        let note = RATE as usize / 4;
+        random(seed)
+            .take(40)
+            .flat_map(|n| {
+                let freq = MIN_FREQ + (n >> 8) as f32 % (MAX_FREQ - MIN_FREQ);
+                (0..note).map(move |i| (2.0 * PI * freq * i as f32 / RATE as f32).sin())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fft_of_a_sine() {
+        let n = 64;
+        let mut buf = (0..n)
+            .map(|i| ((2.0 * PI * 5.0 * i as f32 / n as f32).sin(), 0.0))
+            .collect::<Vec<_>>();
+        fft(&mut buf);
+        let magnitudes = buf
+            .iter()
+            .map(|(re, im)| (re * re + im * im).sqrt())
+            .collect::<Vec<_>>();
+        for (bin, magnitude) in magnitudes.iter().enumerate() {
+            match bin {
+                // half the energy in the bin and half in its mirror image
+                5 | 59 => assert!((magnitude - 32.0).abs() < 0.01, "{bin}: {magnitude}"),
+                _ => assert!(*magnitude < 0.01, "{bin}: {magnitude}"),
+            }
+        }
+    }
+
+    #[test]
+    fn shifted_melody_is_similar() {
+        let song = melody(1);
+        let hashes = hashes(&song);
+        assert!(hashes.len() > MIN_FRAMES);
+        assert!(similar(&hashes, &hashes));
+        // starting a few frames later, like a different encoder delay
+        let later = super::hashes(&song[3 * HOP..]);
+        assert!(similar(&hashes, &later));
+        assert!(similar(&later, &hashes));
+        // a quieter copy
+        let quiet = song.iter().map(|s| s * 0.5).collect::<Vec<_>>();
+        assert!(similar(&hashes, &super::hashes(&quiet)));
+    }
+
+    #[test]
+    fn different_melodies_are_not() {
+        let hashes = hashes(&melody(1));
+        assert!(!similar(&hashes, &super::hashes(&melody(2))));
+        // silence has no changes in energy at all
+        assert!(!similar(
+            &hashes,
+            &super::hashes(&vec![0.0; 10 * RATE as usize])
+        ));
+    }
+
+    #[test]
+    fn offset_search() {
+        let a = random(7).take(100).collect::<Vec<_>>();
+        assert!(similar(&a, &a[MAX_OFFSET as usize..]));
+        assert!(similar(&a[5..], &a));
+        assert!(!similar(&a, &a[MAX_OFFSET as usize + 1..]));
+        // too short to tell
+        assert!(!similar(&a[..MIN_FRAMES - 1], &a[..MIN_FRAMES - 1]));
+        // a few differing bits are fine
+        let mut noisy = a.clone();
+        for n in noisy.iter_mut() {
+            *n ^= 0b1010_0001;
+        }
+        assert!(similar(&a, &noisy));
+    }
+}
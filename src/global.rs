@@ -0,0 +1,206 @@
+use crate::{
+    config::Config,
+    duplicate::Hashes,
+    matching::*,
+    messages::*,
+    release::{self, ReleaseType},
+    song_data::*,
+    InfoTree,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// Findings that need the whole library instead of a single artist.
+pub fn analyze(config: &Config, library: &Artists, hashes: &mut Hashes) -> InfoTree {
+    let mut info = InfoTree::new();
+    if config.cross_artist {
+        cross_artist(config, library, &mut info);
+    }
+    if config.duplicates {
+        identical_files(library, hashes, &mut info);
+    }
+    info
+}
+
+/// Finds songs whose audio is byte for byte identical, wherever they are.
+fn identical_files(library: &Artists, hashes: &mut Hashes, info: &mut InfoTree) {
+    let mut albums = HashMap::<&Path, (&Artist, &String)>::new();
+    for (artist, artist_albums) in library {
+        for (album, (songs, _)) in artist_albums {
+            for song in songs {
+                albums.insert(&song.path, (artist, album));
+            }
+        }
+    }
+    let paths = albums.keys().copied().collect::<Vec<_>>();
+    for group in hashes.identical(&paths) {
+        for path in &group {
+            let (artist, album) = albums[path.as_path()];
+            info.entry(artist.clone())
+                .or_default()
+                .entry(album.clone())
+                .or_default()
+                .push(Info::IdenticalFile(
+                    path.clone(),
+                    group.iter().filter(|p| *p != path).cloned().collect(),
+                ));
+        }
+    }
+}
+
+/// The per artist findings `info` together with the findings of [`analyze`].
+pub fn merge(info: &InfoTree, global: &InfoTree) -> InfoTree {
+    let mut merged = info.clone();
+    for (artist, albums) in global {
+        for (album, fields) in albums {
+            merged
+                .entry(artist.clone())
+                .or_default()
+                .entry(album.clone())
+                .or_default()
+                .extend(fields.iter().cloned());
+        }
+    }
+    merged
+}
+
+/// Finds albums whose songs all appear on albums filed under other artists, matching
+/// songs by their track artist and then like the songs of one artist, so compilations are
+/// matched per track.
+fn cross_artist(config: &Config, library: &Artists, info: &mut InfoTree) {
+    let matcher = Matcher::new(config);
+    let albums = library
+        .iter()
+        .flat_map(|(artist, albums)| {
+            albums.iter().map(|(name, (songs, path))| {
+                let artists = songs
+                    .iter()
+                    .map(|song| {
+                        let artist = song.artist.as_deref().unwrap_or(artist);
+                        match config.normalize {
+                            true => matcher.normalize(artist),
+                            false => artist.to_string(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                CrossAlbum {
+                    artist,
+                    name,
+                    path,
+                    songs,
+                    keys: matcher.keys(songs),
+                    artists,
+                    release: release::of(name, songs),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // the songs of every track artist, by album
+    let mut index = HashMap::<&str, BTreeMap<usize, Vec<usize>>>::new();
+    for (a, album) in albums.iter().enumerate() {
+        for (i, artist) in album.artists.iter().enumerate() {
+            index
+                .entry(artist)
+                .or_default()
+                .entry(a)
+                .or_default()
+                .push(i);
+        }
+    }
+
+    for album in &albums {
+        if album.songs.is_empty() {
+            continue;
+        }
+        // the matching song of every album of another artist, for every song
+        let found = album
+            .songs
+            .iter()
+            .zip(&album.keys)
+            .zip(&album.artists)
+            .map(|(song, artist)| {
+                let mut found = BTreeMap::new();
+                for (b, songs) in &index[artist.as_str()] {
+                    let other = &albums[*b];
+                    if other.artist == album.artist
+                        || !config.reports_subset(album.release, other.release)
+                    {
+                        continue;
+                    }
+                    let candidates = songs
+                        .iter()
+                        .map(|&j| (j, (&other.songs[j], &other.keys[j])));
+                    if let Some(matched) = matcher.find_among(song, candidates) {
+                        found.insert(*b, matched);
+                    }
+                }
+                found
+            })
+            .collect::<Vec<_>>();
+        if found.iter().any(BTreeMap::is_empty) {
+            continue;
+        }
+
+        // pick the albums containing the most remaining songs until every song is covered
+        let mut others = Vec::new();
+        let mut overlaps = Vec::new();
+        let mut left = (0..album.songs.len()).collect::<BTreeSet<_>>();
+        while !left.is_empty() {
+            let mut counts = BTreeMap::<usize, usize>::new();
+            for &i in &left {
+                for b in found[i].keys() {
+                    *counts.entry(*b).or_default() += 1;
+                }
+            }
+            let best = counts
+                .iter()
+                .max_by_key(|(b, count)| {
+                    let other = &albums[**b];
+                    (**count, Reverse((other.artist, other.name)))
+                })
+                .map(|(b, _)| *b)
+                .unwrap();
+            let other = &albums[best];
+            left.retain(|&i| {
+                let Some(&(j, via)) = found[i].get(&best) else {
+                    return true;
+                };
+                overlaps.push(Overlap {
+                    song: album.songs[i].clone(),
+                    other: other.songs[j].clone(),
+                    via,
+                });
+                false
+            });
+            others.push((other.artist.clone(), other.name.clone()));
+        }
+        overlaps.sort_by(|a, b| a.song.path.cmp(&b.song.path));
+        info.entry(album.artist.clone())
+            .or_default()
+            .entry(album.name.clone())
+            .or_default()
+            .push(Info::CrossSubset(
+                album.name.clone(),
+                others,
+                album.path.clone(),
+                overlaps,
+            ));
+    }
+}
+
+/// An album while looking for cross-artist subsets.
+struct CrossAlbum<'a> {
+    artist: &'a Artist,
+    name: &'a String,
+    path: &'a Option<PathBuf>,
+    songs: &'a [Song],
+    /// The comparison keys of the songs, see [`Matcher::keys`].
+    keys: Vec<Option<String>>,
+    /// The track artist of every song, normalized like the titles.
+    artists: Vec<String>,
+    release: ReleaseType,
+}
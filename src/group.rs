@@ -0,0 +1,280 @@
+use crate::{pattern::Pattern, song_data::*};
+use anyhow::{bail, Result};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Artist of songs without an album artist or artist tag.
+pub const UNKNOWN_ARTIST: &str = "-- MISSING ARTIST --";
+
+/// Regroups the songs of `folders` by their album artist (or artist) and album tags.
+/// Songs without an album tag stay grouped by their folder, and copies of an album in
+/// different folders are kept apart so they are still compared with each other.
+pub fn by_tags(roots: &[PathBuf], folders: Albums) -> Artists {
+    let mut groups = BTreeMap::<(Artist, String, PathBuf), Vec<Song>>::new();
+    for (songs, _) in folders.into_values() {
+        for song in songs {
+            let artist = song
+                .album_artist
+                .clone()
+                .or_else(|| song.artist.clone())
+                .unwrap_or(UNKNOWN_ARTIST.to_string());
+            let dir = album_dir(&song.path);
+            let album = song.album.clone().unwrap_or_else(|| {
+                dir.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            });
+            groups.entry((artist, album, dir)).or_default().push(song);
+        }
+    }
+
+    albums(roots, groups)
+}
+
+/// Describes which folders are the artist and album with a pattern per folder, e.g.
+/// `{label}/{artist}/{year} - {album}/{disc}`. Folders after the album are optional.
+#[derive(Debug, Clone)]
+pub struct Layout(Vec<Pattern>);
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let layout = Layout(
+            s.split('/')
+                .filter(|c| !c.is_empty())
+                .map(str::parse)
+                .collect::<Result<_>>()?,
+        );
+        if layout.0.iter().filter(|p| p.has_field("album")).count() != 1 {
+            bail!("the layout {s:?} needs exactly one {{album}}");
+        }
+        Ok(layout)
+    }
+}
+impl Layout {
+    /// The artist, album, album folder and disc number of `song`, `None` if it doesn't fit
+    /// the layout.
+    fn parse(
+        &self,
+        root: &Path,
+        song: &Song,
+    ) -> Option<(Option<String>, String, PathBuf, Option<u16>)> {
+        let dirs = song
+            .path
+            .parent()?
+            .strip_prefix(root)
+            .ok()?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>();
+        let album_at = self.0.iter().position(|p| p.has_field("album"))?;
+        if dirs.len() <= album_at {
+            return None;
+        }
+        let mut fields = HashMap::new();
+        for (pattern, dir) in self.0.iter().zip(&dirs) {
+            fields.extend(pattern.captures(dir)?);
+        }
+        let dir = dirs[..=album_at]
+            .iter()
+            .fold(root.to_path_buf(), |path, dir| path.join(dir.as_ref()));
+        let disc = fields.get("disc").and_then(|d| disc_number(d));
+        Some((fields.remove("artist"), fields.remove("album")?, dir, disc))
+    }
+}
+
+/// Groups the songs of `folders` by the artist and album folders `layout` describes.
+/// Without an `{artist}` in the layout the tags decide the artist, and songs that don't
+/// fit the layout are grouped by their folder.
+pub fn by_layout(roots: &[PathBuf], layout: &Layout, folders: Albums) -> Artists {
+    let mut groups = BTreeMap::<(Artist, String, PathBuf), Vec<Song>>::new();
+    for (songs, _) in folders.into_values() {
+        for mut song in songs {
+            let root = root_of(&song.path, roots);
+            let (artist, album, dir) = match layout.parse(root, &song) {
+                Some((artist, album, dir, disc)) => {
+                    // a disc tag wins over the folder
+                    song.disc = song.disc.or(disc);
+                    (artist, album, dir)
+                }
+                None => {
+                    let dir = song.path.parent().unwrap_or(root).to_path_buf();
+                    let album = match dir.strip_prefix(root) {
+                        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+                        _ => &dir,
+                    };
+                    let album = album.display().to_string();
+                    (None, album, dir)
+                }
+            };
+            let artist = artist
+                .or_else(|| song.album_artist.clone())
+                .or_else(|| song.artist.clone())
+                .unwrap_or(UNKNOWN_ARTIST.to_string());
+            groups.entry((artist, album, dir)).or_default().push(song);
+        }
+    }
+    albums(roots, groups)
+}
+
+/// Turns songs grouped by artist, album and album folder into [`Artists`].
+fn albums(roots: &[PathBuf], groups: BTreeMap<(Artist, String, PathBuf), Vec<Song>>) -> Artists {
+    // a folder can only be removed with an album if no other album lives inside it
+    let mut albums_in = HashMap::<PathBuf, usize>::new();
+    let mut copies = HashMap::<(Artist, String, &Path), usize>::new();
+    for (artist, album, dir) in groups.keys() {
+        let root = root_of(dir, roots);
+        *copies
+            .entry((artist.clone(), album.clone(), root))
+            .or_default() += 1;
+        for dir in dir.ancestors() {
+            *albums_in.entry(dir.to_path_buf()).or_default() += 1;
+            if dir == root {
+                break;
+            }
+        }
+    }
+
+    let mut artists = Artists::new();
+    for ((artist, album, dir), songs) in groups {
+        let root = root_of(&dir, roots);
+        let name = match copies[&(artist.clone(), album.clone(), root)] {
+            1 => album,
+            _ => format!(
+                "{album} ({})",
+                dir.strip_prefix(root).unwrap_or(&dir).display()
+            ),
+        };
+        let name = label(name, root, roots);
+        let path = (albums_in[&dir] == 1 && dir != root).then_some(dir);
+        artists
+            .entry(artist)
+            .or_default()
+            .insert(name, (songs, path));
+    }
+    artists
+}
+
+/// `album` with the root it lives in when scanning several roots, e.g. `Album [flac]`.
+pub fn label(album: String, root: &Path, roots: &[PathBuf]) -> String {
+    if roots.len() < 2 {
+        return album;
+    }
+    let name = |root: &Path| root.file_name().map(|n| n.to_string_lossy().to_string());
+    let label = match roots.iter().filter(|r| name(r) == name(root)).count() {
+        1 => name(root).unwrap_or_default(),
+        _ => root.display().to_string(),
+    };
+    format!("{album} [{label}]")
+}
+
+/// The root `path` lives in.
+fn root_of<'a>(path: &Path, roots: &'a [PathBuf]) -> &'a Path {
+    roots
+        .iter()
+        .find(|root| path.starts_with(root))
+        .map(PathBuf::as_path)
+        .unwrap_or(Path::new("/"))
+}
+
+/// The folder an album lives in, skipping `CD1`/`Disc 2` style folders of multi disc albums.
+fn album_dir(song: &Path) -> PathBuf {
+    let dir = song.parent().unwrap_or(song);
+    match disc_folder(dir) {
+        Some(_) => dir.parent().unwrap_or(dir),
+        None => dir,
+    }
+    .to_path_buf()
+}
+
+/// The disc number of a `CD1`/`Disc 2` style folder.
+pub fn disc_folder(dir: &Path) -> Option<u16> {
+    let name = dir.file_name()?.to_string_lossy().to_lowercase();
+    ["cd", "disc", "disk"].iter().find_map(|prefix| {
+        let n = name.strip_prefix(prefix)?.trim();
+        match n.chars().all(|c| c.is_ascii_digit()) {
+            true => n.parse().ok(),
+            false => None,
+        }
+    })
+}
+
+/// The disc number a `{disc}` folder like `2`, `CD 2` or `Side B` names, going by its last
+/// word. Letters count from `A` as the first disc.
+fn disc_number(name: &str) -> Option<u16> {
+    let last = name
+        .split(|c: char| !c.is_alphanumeric())
+        .rfind(|w| !w.is_empty())?;
+    let digits = last.trim_start_matches(|c: char| c.is_alphabetic());
+    if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        return digits.parse().ok();
+    }
+    let mut chars = last.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => {
+            Some(c.to_ascii_uppercase() as u16 - 'A' as u16 + 1)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(layout: &str, path: &str) -> Option<(Option<String>, String, PathBuf, Option<u16>)> {
+        let layout = layout.parse::<Layout>().unwrap();
+        layout.parse(Path::new("/music"), &Song::new(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn layout_fields() {
+        let layout = "{label}/{artist}/{year} - {album}/{disc}";
+        assert_eq!(
+            parse(layout, "/music/Label/Band/1999 - Album/CD 2/01.flac"),
+            Some((
+                Some("Band".to_string()),
+                "Album".to_string(),
+                PathBuf::from("/music/Label/Band/1999 - Album"),
+                Some(2)
+            ))
+        );
+        // folders after the album are optional
+        assert_eq!(
+            parse(layout, "/music/Label/Band/1999 - Album/01.flac").map(|p| p.3),
+            Some(None)
+        );
+        assert_eq!(parse(layout, "/music/Label/Band/01.flac"), None);
+        assert_eq!(parse(layout, "/music/Label/Band/Album/01.flac"), None);
+        assert_eq!(
+            parse("{album}", "/music/Album/01.flac").map(|p| p.0),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        assert!("{artist}".parse::<Layout>().is_err());
+        assert!("{album}/{album}".parse::<Layout>().is_err());
+        assert!("{artist}/{album".parse::<Layout>().is_err());
+    }
+
+    #[test]
+    fn disc_numbers() {
+        assert_eq!(disc_number("2"), Some(2));
+        assert_eq!(disc_number("CD 2"), Some(2));
+        assert_eq!(disc_number("CD2"), Some(2));
+        assert_eq!(disc_number("Disc 10 (Bonus)"), None);
+        assert_eq!(disc_number("Side B"), Some(2));
+        assert_eq!(disc_number("side a"), Some(1));
+        assert_eq!(disc_number("Bonus"), None);
+        assert_eq!(disc_number(""), None);
+        assert_eq!(disc_folder(Path::new("/a/Disc 3")), Some(3));
+        assert_eq!(disc_folder(Path::new("/a/CD1")), Some(1));
+        assert_eq!(disc_folder(Path::new("/a/CDs")), None);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{messages::*, song_data::Artist, Artists, InfoTree};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fs, path::PathBuf};
+
+/// A finding the user dismissed, identified by its artist, album, kind and the album (or
+/// song) it is about, so it stays dismissed when the collection is scanned again.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Dismissed {
+    pub artist: Artist,
+    /// The folder of the album, see [`album_key`].
+    pub album: String,
+    pub kind: String,
+    pub other: Option<String>,
+}
+impl Dismissed {
+    pub fn new(library: &Artists, artist: &str, album: &str, info: &Info) -> Self {
+        let other = match info {
+            Info::IdenticalFile(path, _) => Some(path.display().to_string()),
+            Info::DuplicateTrack(what, _) => Some(what.clone()),
+            _ => info
+                .other_album()
+                .map(|other| album_key(library, info.other_artist().unwrap_or(artist), other)),
+        };
+        Dismissed {
+            artist: artist.to_string(),
+            album: album_key(library, artist, album),
+            kind: info.kind().to_string(),
+            other,
+        }
+    }
+}
+
+/// The folder of an album, or its name if its songs aren't in a folder of their own. Names
+/// change once a second collection is added, e.g. to `Album [flac]`, folders don't.
+fn album_key(library: &Artists, artist: &str, album: &str) -> String {
+    match library.get(artist).and_then(|albums| albums.get(album)) {
+        Some((_, Some(path))) => path.display().to_string(),
+        _ => album.to_string(),
+    }
+}
+
+/// Dismissed findings, stored in a JSON file between runs.
+pub struct IgnoreList {
+    path: Option<PathBuf>,
+    pub dismissed: BTreeSet<Dismissed>,
+}
+impl IgnoreList {
+    /// Reads the list from `path`, starting empty if it doesn't exist yet.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let dismissed = path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|json| match serde_json::from_slice(&json) {
+                Ok(dismissed) => Some(dismissed),
+                Err(e) => {
+                    eprintln!("Error reading ignore list, starting with an empty one: {e}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        IgnoreList { path, dismissed }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(&self.dismissed)?)?;
+        Ok(())
+    }
+
+    pub fn contains(&self, library: &Artists, artist: &str, album: &str, info: &Info) -> bool {
+        self.dismissed
+            .contains(&Dismissed::new(library, artist, album, info))
+    }
+
+    /// `info` split into the findings to show and the dismissed ones, the albums of
+    /// `library` being where they are found.
+    pub fn split(&self, library: &Artists, info: &InfoTree) -> (InfoTree, InfoTree) {
+        let mut shown = InfoTree::new();
+        let mut hidden = InfoTree::new();
+        for (artist, albums) in info {
+            for (album, fields) in albums {
+                for field in fields {
+                    let tree = match self.contains(library, artist, album, field) {
+                        true => &mut hidden,
+                        false => &mut shown,
+                    };
+                    tree.entry(artist.clone())
+                        .or_default()
+                        .entry(album.clone())
+                        .or_default()
+                        .push(field.clone());
+                }
+            }
+        }
+        (shown, hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Albums;
+
+    #[test]
+    fn dismissed_by_folder() {
+        let library = |label: &str, other: &str| {
+            Artists::from([(
+                "Artist".to_string(),
+                Albums::from([
+                    (
+                        label.to_string(),
+                        (Vec::new(), Some(PathBuf::from("/flac/Artist/Single"))),
+                    ),
+                    (
+                        other.to_string(),
+                        (Vec::new(), Some(PathBuf::from("/flac/Artist/Album"))),
+                    ),
+                    ("Loose".to_string(), (Vec::new(), None)),
+                ]),
+            )])
+        };
+        let subset =
+            |album: &str, of: &str| Info::Subset(album.into(), of.into(), None, Vec::new());
+        let list = IgnoreList {
+            path: None,
+            dismissed: BTreeSet::from([
+                Dismissed::new(
+                    &library("Single", "Album"),
+                    "Artist",
+                    "Single",
+                    &subset("Single", "Album"),
+                ),
+                Dismissed::new(
+                    &library("Single", "Album"),
+                    "Artist",
+                    "Loose",
+                    &subset("Loose", "Album"),
+                ),
+            ]),
+        };
+        // adding a second collection renames the albums, but not their folders
+        let renamed = library("Single [flac]", "Album [flac]");
+        assert!(list.contains(
+            &renamed,
+            "Artist",
+            "Single [flac]",
+            &subset("Single [flac]", "Album [flac]")
+        ));
+        assert!(list.contains(
+            &renamed,
+            "Artist",
+            "Loose",
+            &subset("Loose", "Album [flac]")
+        ));
+        assert!(!list.contains(
+            &renamed,
+            "Artist",
+            "Album [flac]",
+            &subset("Album [flac]", "Single [flac]")
+        ));
+        // another copy of the album in the second collection
+        let mut copy = library("Single [flac]", "Album [flac]");
+        copy.get_mut("Artist").unwrap().insert(
+            "Album [mp3]".to_string(),
+            (Vec::new(), Some(PathBuf::from("/mp3/Artist/Album"))),
+        );
+        assert!(!list.contains(
+            &copy,
+            "Artist",
+            "Single [flac]",
+            &subset("Single [flac]", "Album [mp3]")
+        ));
+    }
+}
@@ -1,231 +1,737 @@
 use anyhow::Result;
-use audiotags::Tag;
 use egui::{CollapsingHeader, Color32, FontId, RichText, ScrollArea, TopBottomPanel, Ui};
 use rayon::prelude::*;
 use std::{
-    collections::BTreeMap,
-    env::args,
+    collections::{BTreeMap, BTreeSet},
     fs,
-    path::PathBuf,
-    sync::mpsc::{channel, Receiver, Sender},
-    thread,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
+mod container;
 mod song_data;
+mod tag_editor;
+mod tag_reader;
+use tag_editor::TagEditor;
+mod unique;
 use song_data::*;
 mod messages;
 use messages::*;
+mod config;
+use config::*;
+mod export;
+mod filename;
+mod matching;
+use matching::*;
+mod audio;
+mod cache;
+mod fingerprint;
+use cache::Cache;
+mod remove;
+use remove::*;
+mod plan;
+use plan::*;
+mod duplicate;
+use duplicate::Hashes;
+mod global;
+mod ignore;
+use ignore::{Dismissed, IgnoreList};
+mod group;
+mod pattern;
+mod quality;
+use quality::{Format, Keep};
+mod release;
+mod report;
+mod watch;
 
 fn get_data(
+    config: &Config,
     recv: Receiver<WorkMessage>,
     sender: &mut Sender<ClientMessage>,
     info_sender: &mut Sender<InfoMessage>,
 ) -> Result<()> {
-    loop {
-        let mut top = Artists::new();
+    let cache = Cache::load(config.cache.clone());
+    let mut roots = Vec::<PathBuf>::new();
+    let mut disconnected = false;
+    // artists found by the last scan when grouping by tags
+    let mut tagged = BTreeSet::<Artist>::new();
+    'scan: while !disconnected {
+        let mut artists = BTreeSet::new();
+        let mut everything = false;
         loop {
-            let WorkMessage::WorkOnFolder(artist) = match recv.recv_timeout(Duration::from_secs(1))
-            {
-                Ok(o) => o,
+            match recv.recv_timeout(Duration::from_secs(1)) {
+                Ok(WorkMessage::WorkOnFolder(artist)) => match artist.file_name() {
+                    Some(name) => _ = artists.insert(name.to_string_lossy().to_string()),
+                    None => eprintln!("Not rescanning {artist:?}, it isn't an artist folder"),
+                },
+                Ok(WorkMessage::Rescan) => everything = true,
+                Ok(WorkMessage::AddRoot(root)) => {
+                    if !roots.contains(&root) {
+                        roots.push(root);
+                        // album names mention their root once there are several
+                        everything = true;
+                    }
+                }
                 Err(e) => match e {
                     std::sync::mpsc::RecvTimeoutError::Timeout => break,
-                    std::sync::mpsc::RecvTimeoutError::Disconnected => return Ok(()),
+                    std::sync::mpsc::RecvTimeoutError::Disconnected => {
+                        // finish the current batch before shutting down
+                        disconnected = true;
+                        break;
+                    }
                 },
             };
-            let mut albums_data = Albums::new();
-            let artist_name = artist.file_name().unwrap().to_string_lossy().to_string();
-
-            let albums = fs::read_dir(artist)?;
-            for album in albums {
-                let mut album_data = Vec::new();
-                let album = album?;
-                if album.path().is_file() {
-                    continue;
-                }
-                let album_name = album.file_name().to_string_lossy().to_string();
+        }
+        if artists.is_empty() && !everything {
+            continue;
+        }
 
-                let mut songs = fs::read_dir(album.path())?.collect::<Vec<_>>();
-                while let Some(song) = songs.pop() {
-                    let song = song?;
-                    if song.path().is_dir() {
-                        songs.extend(fs::read_dir(song.path())?);
-                        continue;
+        let mut top = Artists::new();
+        if config.group_by != GroupBy::Folders {
+            // any file can move songs between albums, so the whole collection is read
+            // again and grouped once its tags are known, see below
+            let mut folders = Albums::new();
+            for root in &roots {
+                match list_folders(root, sender) {
+                    Ok(found) => folders.extend(found),
+                    // every artist of the root would look removed otherwise
+                    Err(e) => {
+                        skipped(sender, root, e);
+                        continue 'scan;
                     }
-                    let song_name = song.file_name().to_string_lossy().to_string();
-                    if is_song(&song_name) {
-                        album_data.push(Song {
-                            name: MISSING.to_string(),
-                            path: song.path(),
-                            unique: false,
-                        });
-                        sender.send(ClientMessage::ArtistLoadingAdd).unwrap();
+                }
+            }
+            top.insert(String::new(), folders);
+        } else {
+            if everything {
+                for root in &roots {
+                    let entries = match fs::read_dir(root) {
+                        Ok(entries) => entries,
+                        Err(e) => {
+                            skipped(sender, root, e);
+                            continue;
+                        }
+                    };
+                    for artist in entries.flatten() {
+                        if artist.path().is_dir() {
+                            artists.insert(artist.file_name().to_string_lossy().to_string());
+                        }
                     }
                 }
-
-                albums_data.insert(album_name, (album_data, album.path()));
             }
-
-            top.insert(artist_name, albums_data);
-        }
-
-        for (artist, mut albums) in top {
-            albums
-                .iter_mut()
-                .par_bridge()
-                .for_each(|(album, (songs, _))| {
-                    let mut new_songs = Vec::new();
-                    for Song { path, .. } in songs.clone() {
-                        let tag = Tag::new().read_from_path(&path).ok();
-                        let name = tag
-                            .as_ref()
-                            .and_then(|v| v.title().map(|x| x.to_string()))
-                            .unwrap_or(MISSING.to_string());
-                        let unique = tag
-                            .as_ref()
-                            .and_then(|v| v.comment().map(|x| x == "unique"))
-                            .unwrap_or_default();
-                        let data = Song {
-                            name: name.clone(),
-                            path: path.clone(),
-                            unique,
+            'artist: for artist in artists {
+                // an artist folder that was removed from every root clears the artist
+                let mut albums_data = Albums::new();
+                for root in &roots {
+                    let dir = root.join(&artist);
+                    if !dir.is_dir() {
+                        continue;
+                    }
+                    sender
+                        .send(ClientMessage::AddArtistPath(artist.clone(), dir.clone()))
+                        .unwrap();
+                    // e.g. removed while it was being scanned, it is scanned again once
+                    // it changes
+                    let albums = match fs::read_dir(&dir) {
+                        Ok(albums) => albums,
+                        Err(e) => {
+                            skipped(sender, &dir, e);
+                            continue 'artist;
+                        }
+                    };
+                    for album in albums.flatten() {
+                        if album.path().is_file() {
+                            continue;
+                        }
+                        let album_name = album.file_name().to_string_lossy().to_string();
+                        // an album missing some of its songs could look like a subset
+                        let album_data = match list_songs(&album.path(), true, sender) {
+                            Ok(songs) => songs,
+                            Err(e) => {
+                                skipped(sender, &album.path(), e);
+                                continue;
+                            }
                         };
-                        sender
-                            .send(ClientMessage::AddSong(
-                                artist.clone(),
-                                album.clone(),
-                                data.clone(),
-                            ))
-                            .unwrap();
-                        new_songs.push(data);
+                        albums_data.insert(
+                            group::label(album_name, root, &roots),
+                            (album_data, Some(album.path())),
+                        );
                     }
+                }
+                top.insert(artist, albums_data);
+            }
+        }
 
-                    *songs = new_songs;
-                });
+        let sidecars = unique::Sidecars::default();
+        for albums in top.values_mut() {
+            albums.iter_mut().par_bridge().for_each(|(_, (songs, _))| {
+                let mut new_songs = Vec::new();
+                for Song { path, .. } in songs.clone() {
+                    let mut data = match cache.get(&path, config.fingerprint, &config.unique) {
+                        Some(song) => song,
+                        None => {
+                            let song = read_song(config, path);
+                            cache.insert(&song, &config.unique);
+                            song
+                        }
+                    };
+                    // not cached, the sidecar and patterns can change without the song changing
+                    data.unique |= sidecars.marks(&data.path, &roots);
+                    filename::infer(&mut data, &config.title_patterns);
+                    sender.send(ClientMessage::SongLoaded).unwrap();
+                    new_songs.push(data);
+                }
+
+                *songs = new_songs;
+            });
+        }
+
+        if let Some(folders) = top.remove("") {
+            top = match &config.layout {
+                Some(layout) => group::by_layout(&roots, layout, folders),
+                None => group::by_tags(&roots, folders),
+            };
+            let found = top.keys().cloned().collect();
+            for gone in std::mem::replace(&mut tagged, found) {
+                top.entry(gone).or_default();
+            }
+        }
+
+        for (artist, albums) in top {
+            sender
+                .send(ClientMessage::SetArtist(artist.clone(), albums.clone()))
+                .unwrap();
             sender.send(ClientMessage::InfoLoadingAdd).unwrap();
             info_sender
                 .send(InfoMessage::Analyze(artist.clone(), albums.clone()))
                 .unwrap();
         }
+        if let Err(e) = cache.save() {
+            eprintln!("Error saving scan cache: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Every song in `dir`, including the ones in sub folders if `recursive`.
+fn list_songs(dir: &Path, recursive: bool, sender: &Sender<ClientMessage>) -> Result<Vec<Song>> {
+    let mut found = Vec::new();
+    let mut songs = fs::read_dir(dir)?.collect::<Vec<_>>();
+    while let Some(song) = songs.pop() {
+        let song = song?;
+        if song.path().is_dir() {
+            if recursive {
+                songs.extend(fs::read_dir(song.path())?);
+            }
+            continue;
+        }
+        let song_name = song.file_name().to_string_lossy().to_string();
+        if is_song(&song_name) {
+            found.push(Song::new(song.path()));
+            sender.send(ClientMessage::ArtistLoadingAdd).unwrap();
+        }
+    }
+    Ok(found)
+}
+
+/// Every folder below `root` containing songs, with the songs directly inside it.
+/// Folders that can't be read are skipped, only failing to read `root` is an error.
+fn list_folders(root: &Path, sender: &Sender<ClientMessage>) -> Result<Albums> {
+    let mut folders = Albums::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if dir == root => return Err(e.into()),
+            Err(e) => {
+                skipped(sender, &dir, e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            if entry.path().is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+        match list_songs(&dir, false, sender) {
+            Ok(songs) if songs.is_empty() => {}
+            Ok(songs) => _ = folders.insert(dir.to_string_lossy().to_string(), (songs, Some(dir))),
+            Err(e) => skipped(sender, &dir, e),
+        }
+    }
+    Ok(folders)
+}
+
+/// Tells the client that `path` couldn't be read and was left out of the scan.
+fn skipped(sender: &Sender<ClientMessage>, path: &Path, e: impl std::fmt::Display) {
+    sender
+        .send(ClientMessage::Skipped(path.to_path_buf(), e.to_string()))
+        .unwrap();
+}
+
+fn read_song(config: &Config, path: PathBuf) -> Song {
+    let tags = tag_reader::read(&path).unwrap_or_default();
+    let unique = config.unique.is_set(&tags);
+    let release_type = release::from_tags(&tags);
+    let properties = audio::properties(&path).ok();
+    let duration = properties.as_ref().and_then(|p| p.duration);
+    let bitrate = duration.filter(|d| *d > 0.0).and_then(|d| {
+        let bits = fs::metadata(&path).ok()?.len() * 8;
+        Some((bits as f64 / d / 1000.0).round() as u32)
+    });
+    Song {
+        name: tags.title.unwrap_or(MISSING.to_string()),
+        title_inferred: false,
+        unique,
+        artist: tags.artist,
+        album_artist: tags.album_artist,
+        album: tags.album,
+        track: tags.track,
+        total_tracks: tags.total_tracks,
+        disc: tags.disc.or_else(|| group::disc_folder(path.parent()?)),
+        total_discs: tags.total_discs,
+        release_type,
+        duration,
+        codec: properties.and_then(|p| p.codec),
+        bitrate,
+        fingerprint: match config.fingerprint {
+            true => fingerprint::compute(&path).ok(),
+            false => None,
+        },
+        path,
     }
 }
 
 type InfoTree = BTreeMap<Artist, BTreeMap<String, Vec<Info>>>;
-fn get_info(
-    sender: &mut Sender<ClientMessage>,
-    artist: String,
-    albums: BTreeMap<String, (Vec<Song>, PathBuf)>,
-) {
+fn get_info(sender: &mut Sender<ClientMessage>, config: &Config, artist: String, albums: Albums) {
+    let mut found = BTreeMap::<String, Vec<Info>>::new();
+    let matcher = Matcher::new(config);
+    let keys = albums
+        .values()
+        .map(|(songs, _)| matcher.keys(songs))
+        .collect::<Vec<_>>();
+    let formats = albums
+        .values()
+        .map(|(songs, _)| Format::of(songs))
+        .collect::<Vec<_>>();
+    let types = albums
+        .iter()
+        .map(|(album, (songs, _))| release::of(album, songs))
+        .collect::<Vec<_>>();
     for (a, (album_a, (songs_a, path))) in albums.iter().enumerate() {
         // Try to find empty albums
         let mut is_empty = false;
         if songs_a.is_empty() {
-            sender
-                .send(ClientMessage::AddInfo(
-                    artist.clone(),
-                    album_a.clone(),
-                    Info::Empty(path.clone()),
-                ))
-                .unwrap();
+            if let Some(path) = path {
+                found
+                    .entry(album_a.clone())
+                    .or_default()
+                    .push(Info::Empty(path.clone()));
+            }
             is_empty = true;
         }
 
         // Find missing names
         let mut missing = Vec::new();
-        for Song { name, path, .. } in songs_a {
-            if name == MISSING {
-                missing.push(path.to_string_lossy().to_string());
+        let mut inferred = Vec::new();
+        for song in songs_a {
+            let path = song.path.to_string_lossy().to_string();
+            if song.name == MISSING {
+                missing.push(path);
+            } else if song.title_inferred {
+                inferred.push((path, song.name.clone()));
             }
         }
         if !missing.is_empty() {
-            sender
-                .send(ClientMessage::AddInfo(
-                    artist.clone(),
-                    album_a.clone(),
-                    Info::MissingTitle(missing),
-                ))
-                .unwrap();
+            found
+                .entry(album_a.clone())
+                .or_default()
+                .push(Info::MissingTitle(missing));
+        }
+        if !inferred.is_empty() {
+            found
+                .entry(album_a.clone())
+                .or_default()
+                .push(Info::InferredTitle(inferred));
+        }
+
+        // Find missing tracks and discs
+        if let Some(info) = gaps(songs_a) {
+            found.entry(album_a.clone()).or_default().push(info);
+        }
+
+        // Find songs that are in the album twice
+        for info in duplicate_tracks(&matcher, songs_a, &keys[a]) {
+            found.entry(album_a.clone()).or_default().push(info);
         }
 
         // find subsets
         if !is_empty {
             for (b, (album_b, (songs_b, _))) in albums.iter().enumerate() {
-                if a == b {
+                if a == b || !config.reports_subset(types[a], types[b]) {
                     continue;
                 }
 
-                let mut overlaps = 0;
                 let mut song_overlaps = Vec::new();
-                for song in songs_a {
-                    if songs_b.contains(song) {
-                        overlaps += 1;
-                        song_overlaps.push(song.clone());
+                for song in songs_a.iter().zip(&keys[a]) {
+                    if let Some((i, via)) = matcher.find(song, songs_b, &keys[b]) {
+                        song_overlaps.push(Overlap {
+                            song: song.0.clone(),
+                            other: songs_b[i].clone(),
+                            via,
+                        });
                     }
                 }
+                let overlaps = song_overlaps.len();
 
-                #[allow(clippy::overly_complex_bool_expr)]
                 if overlaps == songs_a.len() {
-                    sender
-                        .send(ClientMessage::AddInfo(
-                            artist.clone(),
+                    let same_songs = songs_a.len() == songs_b.len();
+                    let info = match (&formats[a], &formats[b]) {
+                        (Some(format_a), Some(format_b)) if same_songs => {
+                            let keep = Keep::compare(format_a, format_b, &config.quality);
+                            match format_a.codec != format_b.codec || keep != Keep::Either {
+                                true => Some(Info::OtherFormat(
+                                    album_a.clone(),
+                                    album_b.clone(),
+                                    (format_a.clone(), format_b.clone()),
+                                    keep,
+                                    path.clone(),
+                                    song_overlaps.clone(),
+                                )),
+                                false => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    found
+                        .entry(album_a.clone())
+                        .or_default()
+                        .push(info.unwrap_or(Info::Subset(
                             album_a.clone(),
-                            Info::Subset(album_a.clone(), album_b.clone(), path.clone()),
-                        ))
-                        .unwrap();
-                } else if overlaps > 0 && false {
-                    sender
-                        .send(ClientMessage::AddInfo(
-                            artist.clone(),
+                            album_b.clone(),
+                            path.clone(),
+                            song_overlaps,
+                        )));
+                } else if config
+                    .partial
+                    .is_some_and(|t| t.reached(overlaps, songs_a.len()))
+                {
+                    found
+                        .entry(album_a.clone())
+                        .or_default()
+                        .push(Info::PartialSubset(
                             album_a.clone(),
-                            Info::PartialSubset(
-                                album_a.clone(),
-                                album_b.clone(),
-                                song_overlaps.into_iter().map(|s| s.name).collect(),
-                            ),
-                        ))
-                        .unwrap();
+                            album_b.clone(),
+                            song_overlaps,
+                            percent(overlaps, songs_a.len()),
+                        ));
                 }
             }
         }
     }
-    sender.send(ClientMessage::InfoLoadingDone).unwrap();
+
+    for (album, covered) in covers(&albums, &found) {
+        found.entry(album).or_default().push(Info::Covers(covered));
+    }
+    sender.send(ClientMessage::SetInfo(artist, found)).unwrap();
+}
+
+/// The fewest albums that together contain every album that is a subset of another, each
+/// with the albums it covers. Picks the album covering the most remaining subsets first.
+fn covers(albums: &Albums, found: &BTreeMap<String, Vec<Info>>) -> Vec<(String, Vec<String>)> {
+    let mut containers = BTreeMap::<&String, BTreeSet<&String>>::new();
+    for info in found.values().flatten() {
+        if let Info::Subset(a, b, _, _) = info {
+            containers.entry(a).or_default().insert(b);
+        }
+    }
+    let mut uncovered = containers.keys().copied().collect::<BTreeSet<_>>();
+    let candidates = containers
+        .values()
+        .flatten()
+        .copied()
+        .collect::<BTreeSet<_>>();
+
+    let mut covers = Vec::new();
+    loop {
+        let best = candidates
+            .iter()
+            .map(|&cover| {
+                let covered = uncovered
+                    .iter()
+                    .filter(|a| **a != cover && containers[*a].contains(cover))
+                    .copied()
+                    .collect::<Vec<_>>();
+                (cover, covered)
+            })
+            // on a tie prefer albums that aren't a subset themselves, then bigger albums
+            .max_by_key(|(cover, covered)| {
+                (
+                    covered.len(),
+                    !uncovered.contains(cover),
+                    albums[*cover].0.len(),
+                )
+            });
+        let Some((cover, covered)) = best.filter(|(_, covered)| !covered.is_empty()) else {
+            break;
+        };
+        for album in &covered {
+            uncovered.remove(album);
+        }
+        uncovered.remove(cover);
+        covers.push((cover.clone(), covered.into_iter().cloned().collect()));
+    }
+    covers
+}
+
+/// Titles matching each other and disc/track numbers used more than once in one album.
+fn duplicate_tracks(matcher: &Matcher, songs: &[Song], keys: &[Option<String>]) -> Vec<Info> {
+    let mut found = Vec::new();
+    let mut seen = vec![false; songs.len()];
+    for i in 0..songs.len() {
+        if seen[i] {
+            continue;
+        }
+        let mut paths = vec![songs[i].path.clone()];
+        for j in i + 1..songs.len() {
+            let matched = matcher.find(
+                (&songs[i], &keys[i]),
+                std::slice::from_ref(&songs[j]),
+                std::slice::from_ref(&keys[j]),
+            );
+            if !seen[j] && matched.is_some() {
+                seen[j] = true;
+                paths.push(songs[j].path.clone());
+            }
+        }
+        if paths.len() > 1 {
+            paths.sort();
+            found.push(Info::DuplicateTrack(format!("{:?}", songs[i].name), paths));
+        }
+    }
+
+    let mut numbers = BTreeMap::<(u16, u16), Vec<PathBuf>>::new();
+    for song in songs {
+        if let Some(track) = song.track {
+            numbers
+                .entry((song.disc.unwrap_or(1), track))
+                .or_default()
+                .push(song.path.clone());
+        }
+    }
+    for ((disc, track), mut paths) in numbers {
+        if paths.len() > 1 {
+            paths.sort();
+            found.push(Info::DuplicateTrack(
+                format!("disc {disc} track {track}"),
+                paths,
+            ));
+        }
+    }
+    found
+}
+
+/// Track numbers missing from the discs of an album and discs missing from the album,
+/// going by the track and disc numbers and totals of its songs.
+fn gaps(songs: &[Song]) -> Option<Info> {
+    let mut discs = BTreeMap::<u16, (BTreeSet<u16>, BTreeSet<u16>)>::new();
+    for song in songs {
+        let Some(track) = song.track else {
+            continue;
+        };
+        let (tracks, totals) = discs.entry(song.disc.unwrap_or(1)).or_default();
+        tracks.insert(track);
+        totals.extend(song.total_tracks.filter(|t| *t > 0));
+    }
+    if discs.is_empty() {
+        return None;
+    }
+
+    let mut problems = Vec::new();
+    let several = discs.len() > 1;
+    for (disc, (tracks, totals)) in &discs {
+        let on = match several {
+            true => format!(" on disc {disc}"),
+            false => String::new(),
+        };
+        if totals.len() > 1 {
+            problems.push(format!(
+                "songs{on} disagree on the number of tracks: {}",
+                totals
+                    .iter()
+                    .map(u16::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        let last = totals
+            .iter()
+            .chain(tracks)
+            .max()
+            .copied()
+            .unwrap_or_default();
+        let missing = (1..=last)
+            .filter(|t| !tracks.contains(t))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            problems.push(format!("missing track {}{on}", ranges(&missing)));
+        }
+    }
+    let total_discs = songs
+        .iter()
+        .filter_map(|s| s.total_discs)
+        .max()
+        .unwrap_or_default();
+    let last = discs
+        .keys()
+        .copied()
+        .max()
+        .unwrap_or_default()
+        .max(total_discs);
+    let missing = (1..=last)
+        .filter(|d| !discs.contains_key(d))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        problems.push(format!("missing disc {}", ranges(&missing)));
+    }
+    (!problems.is_empty()).then_some(Info::Incomplete(problems))
 }
 
-fn main() -> Result<()> {
+/// `1, 3-5` style list of sorted numbers.
+fn ranges(numbers: &[u16]) -> String {
+    let mut ranges = Vec::<(u16, u16)>::new();
+    for &n in numbers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == n => *end = n,
+            _ => ranges.push((n, n)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{start}-{end}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+type Workers = Vec<JoinHandle<Result<()>>>;
+
+/// Spawns the folder listing, tag reading and analysis threads.
+fn spawn_workers(config: &Arc<Config>) -> (Receiver<ClientMessage>, Sender<WorkMessage>, Workers) {
     let (mut sender, reciever) = channel();
     let (work_sender, work_reciever) = channel();
     let (info_sender, info_reciever) = channel();
-    thread::spawn({
-        let work_sender = work_sender.clone();
-        let sender = sender.clone();
-        move || {
-            let artists = fs::read_dir(args().nth(1).unwrap()).unwrap();
-            for artist in artists {
-                let artist = artist.unwrap();
-                sender
-                    .send(ClientMessage::AddArtistPath(
-                        artist.file_name().to_str().unwrap().to_string(),
-                        artist.path(),
-                    ))
-                    .unwrap();
-                work_sender
-                    .send(WorkMessage::WorkOnFolder(artist.path()))
-                    .unwrap();
+    for root in &config.roots {
+        work_sender
+            .send(WorkMessage::AddRoot(root.clone()))
+            .unwrap();
+        if config.watch && !config.headless {
+            if let Err(e) = watch::spawn(root.clone(), config.group_by, work_sender.clone()) {
+                eprintln!("Error watching {root:?}: {e}");
             }
         }
-    });
-    thread::spawn({
+    }
+    let scan = thread::spawn({
+        let config = config.clone();
         let mut sender = sender.clone();
         let mut info_sender = info_sender.clone();
-        move || get_data(work_reciever, &mut sender, &mut info_sender).unwrap()
+        move || get_data(&config, work_reciever, &mut sender, &mut info_sender)
     });
-    thread::spawn(move || {
-        while let Ok(m) = info_reciever.recv() {
-            match m {
-                InfoMessage::Analyze(art, m) => get_info(&mut sender, art, m),
+    let analysis = thread::spawn({
+        let config = config.clone();
+        move || {
+            // every analyzed artist, for the findings that need the whole library
+            let mut library = Artists::new();
+            let mut hashes = Hashes::default();
+            let mut dirty = false;
+            loop {
+                let disconnected = match info_reciever.recv_timeout(Duration::from_secs(1)) {
+                    Ok(InfoMessage::Analyze(art, m)) => {
+                        if config.cross_artist || config.duplicates {
+                            library.insert(art.clone(), m.clone());
+                            dirty = true;
+                        }
+                        get_info(&mut sender, &config, art, m);
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Err(RecvTimeoutError::Disconnected) => true,
+                };
+                if std::mem::take(&mut dirty) {
+                    let info = global::analyze(&config, &library, &mut hashes);
+                    if sender.send(ClientMessage::SetGlobalInfo(info)).is_err() {
+                        return Ok(());
+                    }
+                }
+                if disconnected {
+                    return Ok(());
+                }
             }
         }
     });
+    (reciever, work_sender, vec![scan, analysis])
+}
+
+fn main() -> Result<ExitCode> {
+    let config = Arc::new(Config::from_args()?);
+    let (reciever, work_sender, workers) = spawn_workers(&config);
+    if config.headless {
+        // without our sender the workers shut down once the collection is analyzed
+        drop(work_sender);
+        let ignored = IgnoreList::load(config.ignore_list.clone());
+        let (info, library, skipped) = report::collect(reciever);
+        let (info, dismissed) = ignored.split(&library, &info);
+        // a worker that stopped early or a folder that couldn't be read leaves the
+        // report incomplete
+        let mut failed = false;
+        if skipped > 0 {
+            eprintln!("{skipped} folders couldn't be read, the report is incomplete");
+            failed = true;
+        }
+        for worker in workers {
+            match worker.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    eprintln!("Error scanning the collection: {e}");
+                    failed = true;
+                }
+                Err(_) => {
+                    eprintln!("Scanning the collection failed, the report is incomplete");
+                    failed = true;
+                }
+            }
+        }
+        let problems = report::print(&info);
+        let dismissed = dismissed
+            .values()
+            .flat_map(|a| a.values())
+            .map(Vec::len)
+            .sum::<usize>();
+        if dismissed > 0 {
+            eprintln!("{dismissed} dismissed findings not shown");
+        }
+        // so does an export that couldn't be written
+        for path in &config.exports {
+            if let Err(e) = export::write(path, &info) {
+                eprintln!("Error exporting findings to {path:?}: {e}");
+                failed = true;
+            }
+        }
+        return Ok(match (failed, problems) {
+            (true, _) => ExitCode::from(2),
+            (false, true) => ExitCode::FAILURE,
+            (false, false) => ExitCode::SUCCESS,
+        });
+    }
+
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "subset-album",
@@ -234,16 +740,40 @@ fn main() -> Result<()> {
             Ok(Box::new(App {
                 artists: Default::default(),
                 info: Default::default(),
+                artist_info: Default::default(),
+                dismissed_info: Default::default(),
+                ignored: IgnoreList::load(config.ignore_list.clone()),
+                global_info: Default::default(),
                 reciever,
                 artist_loading_status: (0, 0),
                 info_loading_status: (0, 0),
                 work_sender,
                 artist_paths: Default::default(),
+                analyzed: Default::default(),
+                export_path: None,
+                export_status: None,
+                removal: None,
+                editor: None,
+                remove_mode: RemoveMode::Trash,
+                quarantine: config
+                    .quarantine
+                    .as_ref()
+                    .map(|q| q.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                last_removal: Vec::new(),
+                status: None,
+                roots: config.roots.clone(),
+                new_root: String::new(),
+                config,
+                tab: Tab::Findings,
+                plan: Plan::default(),
+                plan_stale: true,
+                script_path: "remove-albums.sh".to_string(),
             }))
         }),
     )
     .unwrap();
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
 struct App {
@@ -252,11 +782,52 @@ struct App {
     reciever: Receiver<ClientMessage>,
     work_sender: Sender<WorkMessage>,
     artists: Artists,
+    /// Every finding that wasn't dismissed, [`App::artist_info`] and [`App::global_info`] merged.
     info: InfoTree,
+    dismissed_info: InfoTree,
+    ignored: IgnoreList,
+    artist_info: InfoTree,
+    global_info: InfoTree,
     artist_paths: BTreeMap<String, PathBuf>,
+    /// Artists that have been analyzed at least once.
+    analyzed: BTreeSet<Artist>,
+    export_path: Option<String>,
+    export_status: Option<String>,
+    removal: Option<PendingRemoval>,
+    editor: Option<TagEditor>,
+    remove_mode: RemoveMode,
+    quarantine: String,
+    /// The albums removed last, by Quick Remove or the removal plan.
+    last_removal: Vec<(Artist, Removal)>,
+    status: Option<String>,
+    config: Arc<Config>,
+    tab: Tab,
+    plan: Plan,
+    /// Whether the findings changed since the plan was refreshed.
+    plan_stale: bool,
+    script_path: String,
+    roots: Vec<PathBuf>,
+    new_root: String,
+}
+
+#[derive(PartialEq)]
+enum Tab {
+    Findings,
+    Plan,
+    Ignored,
+}
+
+/// Albums waiting for the user to confirm their removal, one from Quick Remove or the
+/// checked albums of the removal plan.
+struct PendingRemoval {
+    /// The artist, folder and files of every album.
+    albums: Vec<(Artist, PathBuf, Vec<PathBuf>)>,
+    plan: bool,
 }
 impl App {
     fn draw_data(&mut self, ui: &mut Ui) {
+        // the artist, the album folder to mark unique or else the songs to mark
+        let mut marked = None;
         ui.columns(2, |ui| {
             ScrollArea::vertical()
                 .auto_shrink([false, false])
@@ -267,10 +838,31 @@ impl App {
                         CollapsingHeader::new(artist)
                             .id_salt(format!("{artist}-info"))
                             .show(ui, |ui| {
-                                for (album, (songs, _)) in albums {
+                                for (album, (songs, path)) in albums {
                                     ui.collapsing(album, |ui| {
+                                        if ui.button("Mark album unique").clicked() {
+                                            marked = Some((
+                                                artist.clone(),
+                                                path.clone(),
+                                                songs.iter().map(|s| s.path.clone()).collect(),
+                                            ));
+                                        }
                                         for song in songs {
-                                            ui.label(&song.name);
+                                            ui.horizontal(|ui| {
+                                                ui.label(&song.name);
+                                                if song.title_inferred {
+                                                    ui.weak("(title from filename)");
+                                                }
+                                                if song.unique {
+                                                    ui.weak("(unique)");
+                                                } else if ui.small_button("Mark unique").clicked() {
+                                                    marked = Some((
+                                                        artist.clone(),
+                                                        None,
+                                                        vec![song.path.clone()],
+                                                    ));
+                                                }
+                                            });
                                         }
                                     });
                                 }
@@ -281,74 +873,79 @@ impl App {
                 .auto_shrink([false, false])
                 .id_salt("overlapps")
                 .show(&mut ui[1], |ui| {
-                    ui.heading("Potential problems:");
+                    ui.horizontal(|ui| {
+                        ui.heading("Potential problems:");
+                        if ui.button("Export…").clicked() {
+                            self.export_path
+                                .get_or_insert_with(|| "findings.json".to_string());
+                        }
+                    });
                     for (artist, tree) in self.info.clone() {
                         ui.collapsing(&artist, |ui| {
                             if ui.button("Reload").clicked() {
-                                let v = self.artists.remove(&artist);
-                                let _ = self.info.remove(&artist);
-                                let songs = v
-                                    .map(|v| v.into_values().map(|(v, _)| v.len()).sum::<usize>())
-                                    .unwrap_or_default();
-                                self.artist_loading_status.0 -= songs;
-                                self.artist_loading_status.1 -= songs;
-                                self.info_loading_status.0 -= 1;
-                                self.info_loading_status.1 -= 1;
-                                self.work_sender
-                                    .send(WorkMessage::WorkOnFolder(
-                                        self.artist_paths.get(&artist).unwrap().clone(),
-                                    ))
-                                    .unwrap();
+                                self.rescan(&artist);
                             }
                             for (album, fields) in tree {
-                                CollapsingHeader::new(album)
+                                CollapsingHeader::new(&album)
                                     .default_open(true)
                                     .show(ui, |ui| {
                                         for field in fields {
-                                            let (text, color, bread, remove_path) = match &field {
-                                                Info::PartialSubset(a, b, songs) => (
-                                                    "Partial subset",
-                                                    Color32::YELLOW,
-                                                    format!(
-                                                        "{a:?} is a partial subset of {b:?}\n\n{}",
-                                                        songs
-                                                            .iter()
-                                                            .map(|s| format!("- {s}"))
-                                                            .collect::<Vec<_>>()
-                                                            .join(",")
-                                                    ),
-                                                    None,
-                                                ),
-                                                Info::MissingTitle(titles) => (
-                                                    "Missing titles",
-                                                    Color32::BLUE,
-                                                    format!("\n{}", titles.join("\n\n")),
-                                                    None,
-                                                ),
-                                                Info::Subset(a, b, path) => (
-                                                    "Subset",
-                                                    Color32::RED,
-                                                    format!("{a:?} is a subset of {b:?}"),
-                                                    Some(path),
-                                                ),
-                                                Info::Empty(path) => (
-                                                    "Empty",
-                                                    Color32::RED,
-                                                    "this album contains no songs".to_string(),
-                                                    Some(path),
-                                                ),
+                                            let color = match &field {
+                                                Info::OtherFormat(_, _, _, Keep::Other, _, _) => {
+                                                    Color32::RED
+                                                }
+                                                Info::PartialSubset(..)
+                                                | Info::OtherFormat(..)
+                                                | Info::IdenticalFile(..)
+                                                | Info::DuplicateTrack(..)
+                                                | Info::Incomplete(_) => Color32::YELLOW,
+                                                Info::Covers(_) => Color32::GREEN,
+                                                Info::MissingTitle(_) | Info::InferredTitle(_) => {
+                                                    Color32::BLUE
+                                                }
+                                                Info::Subset(..)
+                                                | Info::CrossSubset(..)
+                                                | Info::Empty(_) => Color32::RED,
                                             };
+                                            let text = field.kind();
+                                            let bread = field.description();
+                                            let remove_path = field.removable_path();
                                             let label = RichText::new(text).color(color);
                                             ui.horizontal_wrapped(|ui| {
                                                 ui.label(label);
-                                                ui.label(bread);
+                                                match &field {
+                                                    Info::MissingTitle(paths) => {
+                                                        self.missing_titles(ui, &artist, paths)
+                                                    }
+                                                    Info::InferredTitle(songs) => {
+                                                        let paths = songs
+                                                            .iter()
+                                                            .map(|(p, _)| p.clone())
+                                                            .collect::<Vec<_>>();
+                                                        self.missing_titles(ui, &artist, &paths)
+                                                    }
+                                                    _ => _ = ui.label(bread),
+                                                }
+                                                if ui.button("Dismiss").clicked() {
+                                                    let dismissed = Dismissed::new(
+                                                        &self.artists,
+                                                        &artist,
+                                                        &album,
+                                                        &field,
+                                                    );
+                                                    self.ignored.dismissed.insert(dismissed);
+                                                    self.ignored_changed();
+                                                }
                                                 if let Some(remove_path) = remove_path {
                                                     if ui.button("Quick Remove").clicked() {
-                                                        if let Err(e) =
-                                                            std::fs::remove_dir_all(remove_path)
-                                                        {
-                                                            println!("Error removing dir: {e}")
-                                                        }
+                                                        self.removal = Some(PendingRemoval {
+                                                            albums: vec![(
+                                                                artist.clone(),
+                                                                remove_path.clone(),
+                                                                remove::files(remove_path),
+                                                            )],
+                                                            plan: false,
+                                                        });
                                                     }
                                                 };
                                             });
@@ -359,6 +956,446 @@ impl App {
                     }
                 });
         });
+
+        if let Some((artist, dir, songs)) = marked {
+            let marked = match dir {
+                Some(dir) => unique::mark_folder(&dir),
+                None => unique::mark(&songs.iter().map(PathBuf::as_path).collect::<Vec<_>>()),
+            };
+            match marked {
+                Ok(()) => self.rescan(&artist),
+                Err(e) => self.status = Some(format!("Error marking songs unique: {e}")),
+            }
+        }
+    }
+
+    /// Links opening the tag editor for each song of a missing or inferred titles finding.
+    fn missing_titles(&mut self, ui: &mut Ui, artist: &str, paths: &[String]) {
+        ui.vertical(|ui| {
+            for path in paths {
+                if ui.link(path).on_hover_text("Edit tags").clicked() {
+                    self.editor = Some(TagEditor::open(
+                        artist.to_string(),
+                        PathBuf::from(path),
+                        paths.iter().map(PathBuf::from).collect(),
+                    ));
+                }
+            }
+        });
+    }
+
+    fn tag_editor_window(&mut self, ctx: &egui::Context) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        let mut open = true;
+        let mut saved = false;
+        egui::Window::new("Edit tags")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(editor.path.to_string_lossy());
+                egui::Grid::new("tags").num_columns(2).show(ui, |ui| {
+                    for (name, value) in [
+                        ("Title", &mut editor.tags.title),
+                        ("Artist", &mut editor.tags.artist),
+                        ("Album", &mut editor.tags.album),
+                        ("Track", &mut editor.tags.track),
+                    ] {
+                        ui.label(name);
+                        ui.text_edit_singleline(value);
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Filename pattern");
+                    ui.text_edit_singleline(&mut editor.pattern);
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Fill from filename").clicked() {
+                        if let Err(e) = editor.fill_from_filename() {
+                            editor.status = Some(format!("Error: {e}"));
+                        }
+                    }
+                    let all = format!(
+                        "Tag all {} songs from their filenames",
+                        editor.missing.len()
+                    );
+                    if ui.button(all).clicked() {
+                        match editor.fill_all_from_filenames() {
+                            Ok(tagged) => {
+                                self.status =
+                                    Some(format!("Tagged {tagged} songs from their filenames"));
+                                saved = true;
+                            }
+                            Err(e) => editor.status = Some(format!("Error: {e}")),
+                        }
+                    }
+                });
+                if let Some(status) = &editor.status {
+                    ui.label(status);
+                }
+                ui.separator();
+                if ui.button("Save").clicked() {
+                    match editor.save() {
+                        Ok(()) => {
+                            self.status = Some(format!("Saved tags of {:?}", editor.path));
+                            saved = true;
+                        }
+                        Err(e) => editor.status = Some(format!("Error saving tags: {e}")),
+                    }
+                }
+            });
+        if saved {
+            let editor = self.editor.take().unwrap();
+            self.rescan(&editor.artist);
+        } else if !open {
+            self.editor = None;
+        }
+    }
+
+    fn rescan(&self, artist: &str) {
+        let message = match self.config.group_by {
+            GroupBy::Folders => match self.artist_paths.get(artist) {
+                Some(path) => WorkMessage::WorkOnFolder(path.clone()),
+                None => return,
+            },
+            GroupBy::Tags | GroupBy::Layout => WorkMessage::Rescan,
+        };
+        self.work_sender.send(message).unwrap();
+    }
+
+    fn removal_window(&mut self, ctx: &egui::Context) {
+        let Some(pending) = self.removal.take() else {
+            return;
+        };
+        let mut open = true;
+        let mut confirmed = false;
+        let title = match pending.albums.len() {
+            1 => "Remove album?".to_string(),
+            n => format!("Remove {n} albums?"),
+        };
+        egui::Window::new(title)
+            .id(egui::Id::new("remove-albums"))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (_, path, files) in &pending.albums {
+                        ui.label(format!("{path:?}"));
+                        ui.label(format!("contains {} files:", files.len()));
+                        ui.indent(path, |ui| {
+                            for file in files {
+                                let file = file.strip_prefix(path).unwrap_or(file);
+                                ui.label(file.to_string_lossy());
+                            }
+                        });
+                    }
+                });
+                ui.separator();
+                self.remove_mode_picker(ui);
+                confirmed = ui.button("Remove").clicked();
+            });
+        if !confirmed {
+            if open {
+                self.removal = Some(pending);
+            }
+            return;
+        }
+
+        let quarantine = PathBuf::from(&self.quarantine);
+        let albums = pending
+            .albums
+            .iter()
+            .map(|(artist, path, _)| (artist.clone(), path.clone()))
+            .collect::<Vec<_>>();
+        self.last_removal = match pending.plan {
+            true => {
+                let removed = self
+                    .plan
+                    .apply(&albums, self.remove_mode, &self.roots, &quarantine);
+                self.status = Some(format!(
+                    "Removed {} of {} albums, see the log below",
+                    removed.len(),
+                    albums.len()
+                ));
+                removed
+            }
+            false => {
+                let (artist, path) = &albums[0];
+                match remove::remove(path, self.remove_mode, &self.roots, &quarantine) {
+                    Ok(removal) => {
+                        self.status = Some(format!("Successfully {removal}"));
+                        self.plan.removed.insert(path.clone(), artist.clone());
+                        vec![(artist.clone(), removal)]
+                    }
+                    Err(e) => {
+                        self.status = Some(format!("Error removing {path:?}: {e}"));
+                        Vec::new()
+                    }
+                }
+            }
+        };
+        for artist in albums.iter().map(|(a, _)| a).collect::<BTreeSet<_>>() {
+            self.rescan(artist);
+        }
+    }
+
+    fn remove_mode_picker(&mut self, ui: &mut Ui) {
+        for mode in [
+            RemoveMode::Trash,
+            RemoveMode::Quarantine,
+            RemoveMode::Delete,
+        ] {
+            ui.radio_value(&mut self.remove_mode, mode, mode.label());
+        }
+        if self.remove_mode == RemoveMode::Quarantine {
+            ui.horizontal(|ui| {
+                ui.label("Quarantine folder:");
+                ui.text_edit_singleline(&mut self.quarantine);
+            });
+        }
+        if self.remove_mode == RemoveMode::Delete {
+            ui.colored_label(Color32::RED, "This can't be undone!");
+        }
+    }
+
+    fn draw_plan(&mut self, ui: &mut Ui) {
+        if std::mem::take(&mut self.plan_stale) {
+            self.plan.refresh(&self.info);
+        }
+        ui.heading("Removal plan:");
+        let selected = self.plan.checked().count();
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{selected} of {} albums selected, removing them frees {}",
+                self.plan.items.len(),
+                human_size(self.plan.total_size())
+            ));
+            if ui.button("Select all").clicked() {
+                self.plan.items.values_mut().for_each(|i| i.checked = true);
+            }
+            if ui.button("Select none").clicked() {
+                self.plan.items.values_mut().for_each(|i| i.checked = false);
+            }
+        });
+        self.remove_mode_picker(ui);
+        let quarantine = PathBuf::from(&self.quarantine);
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.script_path);
+            if ui.button("Export as script").clicked() {
+                let script = self.plan.script(self.remove_mode, &self.roots, &quarantine);
+                self.status = Some(match fs::write(&self.script_path, script) {
+                    Ok(()) => format!("Saved removal script to {}", self.script_path),
+                    Err(e) => format!("Error saving removal script: {e}"),
+                });
+            }
+            let apply = ui.add_enabled(selected > 0, egui::Button::new("Apply…"));
+            if apply.clicked() {
+                let albums = self
+                    .plan
+                    .checked()
+                    .map(|(path, item)| (item.artist.clone(), path.clone(), remove::files(path)))
+                    .collect();
+                self.removal = Some(PendingRemoval { albums, plan: true });
+            }
+        });
+        ui.separator();
+        ScrollArea::vertical()
+            .auto_shrink([false, true])
+            .max_height(ui.available_height() * 0.7)
+            .id_salt("plan")
+            .show(ui, |ui| {
+                for (path, item) in &mut self.plan.items {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(
+                            &mut item.checked,
+                            format!("{} / {}", item.artist, item.album),
+                        );
+                        ui.weak(format!("{} - {path:?}", human_size(item.size)));
+                    });
+                    if item.mutual {
+                        ui.indent(path, |ui| {
+                            ui.colored_label(
+                                Color32::YELLOW,
+                                "has the same songs as another album, which is kept",
+                            )
+                        });
+                    }
+                    for reason in &item.reasons {
+                        ui.indent(path, |ui| ui.weak(reason));
+                    }
+                }
+            });
+        CollapsingHeader::new(format!("Log ({})", self.plan.log.len()))
+            .id_salt("plan-log")
+            .show(ui, |ui| {
+                for line in &self.plan.log {
+                    ui.label(line);
+                }
+            });
+    }
+
+    fn status_bar(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if let Some(status) = &self.status {
+                ui.label(status);
+            }
+            let undo = match self.last_removal.iter().any(|(_, r)| r.can_undo()) {
+                true => ui.button("Undo").clicked(),
+                false => false,
+            };
+            if undo {
+                let mut artists = BTreeSet::new();
+                let mut restored = Vec::new();
+                for (artist, removal) in std::mem::take(&mut self.last_removal) {
+                    if !removal.can_undo() {
+                        continue;
+                    }
+                    restored.push(match removal.undo() {
+                        Ok(()) => format!("Restored {:?}", removal.path()),
+                        Err(e) => format!("Error restoring {:?}: {e}", removal.path()),
+                    });
+                    artists.insert(artist);
+                }
+                self.status = Some(restored.join(", "));
+                for artist in artists {
+                    self.rescan(&artist);
+                }
+            }
+        });
+    }
+
+    fn collections_bar(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let roots = self
+                .roots
+                .iter()
+                .map(|r| r.to_string_lossy())
+                .collect::<Vec<_>>();
+            ui.label(format!("Collections: {}", roots.join(", ")));
+            ui.text_edit_singleline(&mut self.new_root);
+            if ui.button("Add collection").clicked() {
+                let root = PathBuf::from(self.new_root.trim());
+                if !root.is_dir() {
+                    self.status = Some(format!("{root:?} is not a directory"));
+                } else if !self.roots.contains(&root) {
+                    self.roots.push(root.clone());
+                    self.work_sender
+                        .send(WorkMessage::AddRoot(root.clone()))
+                        .unwrap();
+                    if self.config.watch {
+                        if let Err(e) = watch::spawn(
+                            root.clone(),
+                            self.config.group_by,
+                            self.work_sender.clone(),
+                        ) {
+                            self.status = Some(format!("Error watching {root:?}: {e}"));
+                        }
+                    }
+                    self.new_root.clear();
+                }
+            }
+        });
+    }
+
+    fn draw_ignored(&mut self, ui: &mut Ui) {
+        ui.heading("Dismissed findings:");
+        let mut restored = None;
+        ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .id_salt("ignored")
+            .show(ui, |ui| {
+                for dismissed in &self.ignored.dismissed {
+                    ui.horizontal_wrapped(|ui| {
+                        if ui.button("Restore").clicked() {
+                            restored = Some(dismissed.clone());
+                        }
+                        ui.label(format!("{} / {}", dismissed.artist, dismissed.album));
+                        ui.label(RichText::new(&dismissed.kind).color(Color32::GRAY));
+                        if let Some(other) = &dismissed.other {
+                            ui.label(other);
+                        }
+                    });
+                    // the findings it still hides, nothing if it was resolved since
+                    let hidden = self
+                        .dismissed_info
+                        .get(&dismissed.artist)
+                        .into_iter()
+                        .flatten()
+                        .flat_map(|(album, fields)| fields.iter().map(move |f| (album, f)))
+                        .filter(|(album, f)| {
+                            Dismissed::new(&self.artists, &dismissed.artist, album, f) == *dismissed
+                        });
+                    for (_, field) in hidden {
+                        ui.indent(dismissed, |ui| ui.weak(field.description()));
+                    }
+                }
+            });
+        if let Some(restored) = restored {
+            self.ignored.dismissed.remove(&restored);
+            self.ignored_changed();
+        }
+    }
+
+    /// Saves the ignore list and moves findings between the findings and ignored tabs.
+    fn ignored_changed(&mut self) {
+        if let Err(e) = self.ignored.save() {
+            self.status = Some(format!("Error saving ignore list: {e}"));
+        }
+        self.refresh_info();
+    }
+
+    fn refresh_info(&mut self) {
+        let info = global::merge(&self.artist_info, &self.global_info);
+        (self.info, self.dismissed_info) = self.ignored.split(&self.artists, &info);
+        self.plan_stale = true;
+    }
+
+    /// [`App::refresh_info`] for the findings of a single artist, so an artist being
+    /// analyzed doesn't copy the findings of every other one.
+    fn refresh_artist(&mut self, artist: &Artist) {
+        let slice = |tree: &InfoTree| {
+            tree.get_key_value(artist)
+                .map(|(artist, albums)| (artist.clone(), albums.clone()))
+                .into_iter()
+                .collect::<InfoTree>()
+        };
+        let info = global::merge(&slice(&self.artist_info), &slice(&self.global_info));
+        let (shown, hidden) = self.ignored.split(&self.artists, &info);
+        for (tree, found) in [(&mut self.info, shown), (&mut self.dismissed_info, hidden)] {
+            tree.remove(artist);
+            tree.extend(found);
+        }
+        self.plan_stale = true;
+    }
+
+    fn export_window(&mut self, ctx: &egui::Context) {
+        let Some(path) = &mut self.export_path else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Export findings")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label("Save to (.json or .csv):");
+                ui.text_edit_singleline(path);
+                if ui.button("Save").clicked() {
+                    self.export_status = Some(match export::write(Path::new(path), &self.info) {
+                        Ok(()) => format!("Saved findings to {path}"),
+                        Err(e) => format!("Error exporting: {e}"),
+                    });
+                }
+                if let Some(status) = &self.export_status {
+                    ui.label(status);
+                }
+            });
+        if !open {
+            self.export_path = None;
+            self.export_status = None;
+        }
     }
 
     fn progress_bar(&self, ui: &mut Ui, title: &str, (cur, max): (usize, usize)) {
@@ -390,27 +1427,41 @@ impl eframe::App for App {
             match m {
                 ClientMessage::ArtistLoadingAdd => self.artist_loading_status.1 += 1,
                 ClientMessage::InfoLoadingAdd => self.info_loading_status.1 += 1,
-                ClientMessage::InfoLoadingDone => self.info_loading_status.0 += 1,
+                ClientMessage::SongLoaded => self.artist_loading_status.0 += 1,
                 ClientMessage::AddArtistPath(artist, path) => {
                     self.artist_paths.insert(artist, path);
                 }
-                ClientMessage::AddInfo(artist, album, info) => {
-                    self.info
-                        .entry(artist)
-                        .or_default()
-                        .entry(album)
-                        .or_default()
-                        .push(info);
+                ClientMessage::SetArtist(artist, albums) => {
+                    // the songs of a rescanned artist have been counted again
+                    let old = match albums.is_empty() {
+                        true => self.artists.remove(&artist),
+                        false => self.artists.insert(artist, albums),
+                    };
+                    let songs = old
+                        .map(|v| v.into_values().map(|(v, _)| v.len()).sum::<usize>())
+                        .unwrap_or_default();
+                    self.artist_loading_status.0 -= songs;
+                    self.artist_loading_status.1 -= songs;
                 }
-                ClientMessage::AddSong(artist, album, song) => {
-                    self.artist_loading_status.0 += 1;
-                    self.artists
-                        .entry(artist)
-                        .or_default()
-                        .entry(album)
-                        .or_default()
-                        .0
-                        .push(song);
+                ClientMessage::SetInfo(artist, found) => {
+                    match self.analyzed.insert(artist.clone()) {
+                        true => self.info_loading_status.0 += 1,
+                        false => self.info_loading_status.1 -= 1,
+                    }
+                    match found.is_empty() {
+                        true => _ = self.artist_info.remove(&artist),
+                        false => _ = self.artist_info.insert(artist.clone(), found),
+                    }
+                    self.plan.analyzed(&artist);
+                    self.refresh_artist(&artist);
+                }
+                ClientMessage::SetGlobalInfo(found) => {
+                    self.global_info = found;
+                    self.refresh_info();
+                }
+                ClientMessage::Skipped(path, e) => {
+                    eprintln!("Error reading {path:?}, skipping it: {e}");
+                    self.status = Some(format!("Error reading {path:?}, skipping it: {e}"));
                 }
             }
 
@@ -429,12 +1480,332 @@ impl eframe::App for App {
                 TopBottomPanel::top("top-panel").show(ctx, |ui| {
                     self.progress_bar(ui, "Mapping artists", self.artist_loading_status);
                     self.progress_bar(ui, " Finding faults", self.info_loading_status);
+                    self.status_bar(ui);
+                    self.collections_bar(ui);
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.tab, Tab::Findings, "Findings");
+                        ui.selectable_value(&mut self.tab, Tab::Plan, "Removal plan");
+                        let ignored = format!("Ignored ({})", self.ignored.dismissed.len());
+                        ui.selectable_value(&mut self.tab, Tab::Ignored, ignored);
+                    });
                 });
-                egui::CentralPanel::default().show(ctx, |ui| {
-                    self.draw_data(ui);
+                egui::CentralPanel::default().show(ctx, |ui| match self.tab {
+                    Tab::Findings => self.draw_data(ui),
+                    Tab::Plan => self.draw_plan(ui),
+                    Tab::Ignored => self.draw_ignored(ui),
                 });
+                self.export_window(ctx);
+                self.removal_window(ctx);
+                self.tag_editor_window(ctx);
             }
         });
         ctx.request_repaint_after(Duration::from_secs_f64(0.066));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(disc: Option<u16>, track: u16, total: Option<u16>) -> Song {
+        let mut song = Song::new(PathBuf::from(format!("{disc:?}-{track}")));
+        song.disc = disc;
+        song.track = Some(track);
+        song.total_tracks = total;
+        song
+    }
+
+    fn incomplete(songs: &[Song]) -> Option<Vec<String>> {
+        match gaps(songs)? {
+            Info::Incomplete(problems) => Some(problems),
+            info => panic!("unexpected {info:?}"),
+        }
+    }
+
+    #[test]
+    fn ranges_of_numbers() {
+        assert_eq!(ranges(&[]), "");
+        assert_eq!(ranges(&[4]), "4");
+        assert_eq!(ranges(&[1, 3, 4, 5, 7, 8]), "1, 3-5, 7-8");
+    }
+
+    #[test]
+    fn missing_tracks() {
+        assert_eq!(
+            incomplete(&[song(None, 1, None), song(None, 2, None)]),
+            None
+        );
+        assert_eq!(incomplete(&[]), None);
+        assert_eq!(
+            incomplete(&[song(None, 2, None), song(None, 5, None)]),
+            Some(vec!["missing track 1, 3-4".to_string()])
+        );
+        // the total counts when it is larger than the last track
+        assert_eq!(
+            incomplete(&[song(None, 1, Some(3)), song(None, 2, Some(0))]),
+            Some(vec!["missing track 3".to_string()])
+        );
+        assert_eq!(
+            incomplete(&[song(None, 1, Some(2)), song(None, 2, Some(3))]),
+            Some(vec![
+                "songs disagree on the number of tracks: 2, 3".to_string(),
+                "missing track 3".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn missing_discs() {
+        assert_eq!(
+            incomplete(&[song(Some(1), 1, None), song(Some(3), 2, None)]),
+            Some(vec![
+                "missing track 1 on disc 3".to_string(),
+                "missing disc 2".to_string()
+            ])
+        );
+        let mut last = song(Some(1), 1, None);
+        last.total_discs = Some(2);
+        assert_eq!(
+            incomplete(&[last]),
+            Some(vec!["missing disc 2".to_string()])
+        );
+    }
+
+    /// What `duplicate_tracks` finds among `songs`, each found once with the paths involved.
+    fn duplicates(songs: &[Song]) -> Vec<(String, Vec<String>)> {
+        let config = Config::default();
+        let matcher = Matcher::new(&config);
+        let keys = matcher.keys(songs);
+        duplicate_tracks(&matcher, songs, &keys)
+            .into_iter()
+            .map(|info| match info {
+                Info::DuplicateTrack(what, paths) => (
+                    what,
+                    paths
+                        .iter()
+                        .map(|p| p.to_string_lossy().to_string())
+                        .collect(),
+                ),
+                info => panic!("unexpected {info:?}"),
+            })
+            .collect()
+    }
+
+    fn on_disc(disc: u16, track: u16, title: &str) -> Song {
+        let mut song = Song::new(PathBuf::from(format!("CD{disc}/{track} {title}")));
+        song.name = title.to_string();
+        song.disc = Some(disc);
+        song.track = Some(track);
+        song
+    }
+
+    #[test]
+    fn duplicate_titles_and_numbers() {
+        assert_eq!(
+            duplicates(&[on_disc(1, 1, "Intro"), on_disc(1, 2, "Song")]),
+            vec![]
+        );
+        // a track copied into both discs
+        assert_eq!(
+            duplicates(&[
+                on_disc(1, 3, "Song"),
+                on_disc(2, 3, "Song (Remastered)"),
+                on_disc(2, 1, "Other")
+            ]),
+            vec![(
+                "\"Song\"".to_string(),
+                vec![
+                    "CD1/3 Song".to_string(),
+                    "CD2/3 Song (Remastered)".to_string()
+                ]
+            )]
+        );
+        // twice on the same disc, once with the number of another track
+        assert_eq!(
+            duplicates(&[
+                on_disc(1, 1, "Song"),
+                on_disc(1, 2, "Other"),
+                on_disc(1, 2, "Song")
+            ]),
+            vec![
+                (
+                    "\"Song\"".to_string(),
+                    vec!["CD1/1 Song".to_string(), "CD1/2 Song".to_string()]
+                ),
+                (
+                    "disc 1 track 2".to_string(),
+                    vec!["CD1/2 Other".to_string(), "CD1/2 Song".to_string()]
+                )
+            ]
+        );
+        // songs marked unique never match
+        let mut unique = on_disc(2, 1, "Intro");
+        unique.unique = true;
+        assert_eq!(duplicates(&[on_disc(1, 1, "Intro"), unique]), vec![]);
+    }
+
+    fn covered(albums: &[(&str, usize)], subsets: &[(&str, &str)]) -> Vec<(String, Vec<String>)> {
+        let albums = albums
+            .iter()
+            .map(|(name, songs)| {
+                let songs = (0..*songs)
+                    .map(|i| song(None, i as u16 + 1, None))
+                    .collect();
+                (name.to_string(), (songs, None))
+            })
+            .collect::<Albums>();
+        let mut found = BTreeMap::<String, Vec<Info>>::new();
+        for (a, b) in subsets {
+            found.entry(a.to_string()).or_default().push(Info::Subset(
+                a.to_string(),
+                b.to_string(),
+                None,
+                Vec::new(),
+            ));
+        }
+        covers(&albums, &found)
+    }
+
+    fn cover(album: &str, covered: &[&str]) -> (String, Vec<String>) {
+        (
+            album.to_string(),
+            covered.iter().map(|a| a.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn fewest_covering_albums() {
+        assert_eq!(covered(&[("Album", 10)], &[]), vec![]);
+        // the deluxe edition covers everything the album does and more
+        assert_eq!(
+            covered(
+                &[("Album", 10), ("Deluxe", 14), ("EP", 4), ("Single", 1)],
+                &[
+                    ("Single", "EP"),
+                    ("Single", "Album"),
+                    ("Single", "Deluxe"),
+                    ("EP", "Deluxe"),
+                    ("Album", "Deluxe"),
+                ]
+            ),
+            vec![cover("Deluxe", &["Album", "EP", "Single"])]
+        );
+        // albums with the same songs cover each other, the one covering more is picked even
+        // if it is smaller
+        assert_eq!(
+            covered(
+                &[("A", 10), ("B", 11), ("Single", 1)],
+                &[("A", "B"), ("B", "A"), ("Single", "A")]
+            ),
+            vec![cover("A", &["B", "Single"])]
+        );
+        // when they cover as many albums the bigger one is picked
+        assert_eq!(
+            covered(
+                &[("A", 10), ("B", 11), ("Single", 1)],
+                &[("A", "B"), ("B", "A"), ("Single", "A"), ("Single", "B")]
+            ),
+            vec![cover("B", &["A", "Single"])]
+        );
+        assert_eq!(
+            covered(
+                &[("A", 12), ("B", 11), ("Single", 1)],
+                &[("Single", "A"), ("Single", "B")]
+            ),
+            vec![cover("A", &["Single"])]
+        );
+        assert_eq!(
+            covered(
+                &[("A", 10), ("B", 10), ("Live", 5), ("Single", 1)],
+                &[("Live", "A"), ("Single", "B")]
+            ),
+            vec![cover("B", &["Single"]), cover("A", &["Live"])]
+        );
+    }
+
+    /// The albums of other artists the "Greatest Hits" compilation is found to be made of.
+    fn cross_subset(config: &Config, library: &Artists) -> Vec<(Artist, String)> {
+        let info = global::analyze(config, library, &mut Hashes::default());
+        match info
+            .get("Various Artists")
+            .and_then(|a| a.get("Greatest Hits"))
+        {
+            Some(fields) => match &fields[..] {
+                [Info::CrossSubset(_, others, _, _)] => others.clone(),
+                fields => panic!("unexpected {fields:?}"),
+            },
+            None => Vec::new(),
+        }
+    }
+
+    fn track(title: &str, artist: &str, duration: f64) -> Song {
+        let mut song = Song::new(PathBuf::from(format!("{artist}/{title}")));
+        song.name = title.to_string();
+        song.artist = Some(artist.to_string());
+        song.duration = Some(duration);
+        song
+    }
+
+    #[test]
+    fn cross_artist_subsets() {
+        let album = |songs: Vec<Song>| (songs, None);
+        let library = |intro: f64| {
+            Artists::from([
+                (
+                    "Various Artists".to_string(),
+                    Albums::from([(
+                        "Greatest Hits".to_string(),
+                        album(vec![
+                            track("Song (Remastered)", "Band", 200.0),
+                            track("Intro", "Singer", intro),
+                        ]),
+                    )]),
+                ),
+                (
+                    "Band".to_string(),
+                    Albums::from([(
+                        "Album".to_string(),
+                        album(vec![
+                            track("Song", "Band", 201.0),
+                            track("Other", "Band", 100.0),
+                        ]),
+                    )]),
+                ),
+                (
+                    "Singer".to_string(),
+                    Albums::from([(
+                        "Intro (Single)".to_string(),
+                        album(vec![track("Intro", "Singer", 60.0)]),
+                    )]),
+                ),
+            ])
+        };
+        let config = Config {
+            cross_artist: true,
+            ..Config::default()
+        };
+        let both = vec![
+            ("Band".to_string(), "Album".to_string()),
+            ("Singer".to_string(), "Intro (Single)".to_string()),
+        ];
+        assert_eq!(cross_subset(&config, &library(61.0)), both);
+        // the intro of the compilation is a different song
+        assert_eq!(cross_subset(&config, &library(90.0)), vec![]);
+        let ignore = Config {
+            duration_tolerance: None,
+            ..config.clone()
+        };
+        assert_eq!(cross_subset(&ignore, &library(90.0)), both);
+        // compilations made of singles aren't reported
+        let rules = Config {
+            ignore_subsets: vec!["compilation:single".parse().unwrap()],
+            ..config.clone()
+        };
+        assert_eq!(cross_subset(&rules, &library(61.0)), vec![]);
+        let exact = Config {
+            normalize: false,
+            ..config
+        };
+        assert_eq!(cross_subset(&exact, &library(61.0)), vec![]);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{config::Config, fingerprint, song_data::*};
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+/// Words after which everything outside of brackets is dropped, e.g. `Song feat. Someone`.
+const FEATURING: &[&str] = &["feat", "ft", "featuring"];
+
+/// How two songs were found to be the same, from most to least certain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum MatchKind {
+    Exact,
+    Normalized,
+    Fingerprint,
+    Fuzzy,
+}
+impl MatchKind {
+    pub fn label(&self) -> Option<&'static str> {
+        match self {
+            MatchKind::Exact => None,
+            MatchKind::Normalized => Some("matched via normalization"),
+            MatchKind::Fingerprint => Some("matched via audio fingerprint"),
+            MatchKind::Fuzzy => Some("matched via edit distance"),
+        }
+    }
+}
+
+/// A song from one album and the song it matched in the other.
+#[derive(Debug, Clone, Serialize)]
+pub struct Overlap {
+    pub song: Song,
+    pub other: Song,
+    pub via: MatchKind,
+}
+
+pub struct Matcher<'a> {
+    config: &'a Config,
+}
+impl<'a> Matcher<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self { config }
+    }
+
+    /// Case folds, unicode normalizes, strips suffixes like `(Remastered 2011)` or
+    /// `- Live` and collapses punctuation.
+    pub fn normalize(&self, title: &str) -> String {
+        let title = title.nfkc().collect::<String>().to_lowercase();
+
+        // drop bracketed groups containing one of the suffixes
+        let mut stripped = String::new();
+        let mut group = String::new();
+        let mut depth = 0;
+        for c in title.chars() {
+            match c {
+                '(' | '[' | '{' => {
+                    depth += 1;
+                    group.push(c);
+                }
+                ')' | ']' | '}' if depth > 0 => {
+                    depth -= 1;
+                    group.push(c);
+                    if depth == 0 {
+                        if !self.has_suffix(&group) {
+                            stripped.push_str(&group);
+                        }
+                        group.clear();
+                    }
+                }
+                _ if depth > 0 => group.push(c),
+                _ => stripped.push(c),
+            }
+        }
+        stripped.push_str(&group);
+
+        // drop ` - Remastered 2011` style segments
+        let mut segments = stripped.split(" - ");
+        let mut title = segments.next().unwrap_or_default().to_string();
+        for segment in segments {
+            if !self.has_suffix(segment) {
+                title.push_str(" - ");
+                title.push_str(segment);
+            }
+        }
+
+        let words = collapse(&title);
+        let mut words = words.split(' ').collect::<Vec<_>>();
+        if let Some(i) = words.iter().skip(1).position(|w| FEATURING.contains(w)) {
+            words.truncate(i + 1);
+        }
+        words.join(" ")
+    }
+
+    /// Whether `text` contains one of the suffixes as whole words, e.g. `2011 Remastered`
+    /// but not `Liverpool`.
+    fn has_suffix(&self, text: &str) -> bool {
+        let text = collapse(text);
+        let words = text.split(' ').collect::<Vec<_>>();
+        self.config.suffixes.iter().any(|suffix| {
+            let suffix = collapse(suffix);
+            let suffix = suffix.split(' ').collect::<Vec<_>>();
+            !suffix[0].is_empty()
+                && words
+                    .windows(suffix.len())
+                    .any(|w| w.iter().zip(&suffix).all(|(w, s)| inflects(w, s)))
+        })
+    }
+
+    /// The comparison key of every song, `None` for songs that never match.
+    pub fn keys(&self, songs: &[Song]) -> Vec<Option<String>> {
+        songs
+            .iter()
+            .map(|song| match song.unique || song.name == MISSING {
+                true => None,
+                false if self.config.normalize => Some(self.normalize(&song.name)),
+                false => Some(song.name.clone()),
+            })
+            .collect()
+    }
+
+    /// Finds the song in `songs` that matches `song`, preferring exact matches.
+    /// When both songs have fingerprints their audio decides, not their titles.
+    pub fn find(
+        &self,
+        song: (&Song, &Option<String>),
+        songs: &[Song],
+        keys: &[Option<String>],
+    ) -> Option<(usize, MatchKind)> {
+        self.find_among(song, songs.iter().zip(keys).enumerate())
+    }
+
+    /// [`Matcher::find`] among `candidates`, returning the index of the one that matched.
+    pub fn find_among<'s>(
+        &self,
+        (song, key): (&Song, &Option<String>),
+        candidates: impl IntoIterator<Item = (usize, (&'s Song, &'s Option<String>))>,
+    ) -> Option<(usize, MatchKind)> {
+        if song.unique {
+            return None;
+        }
+        let mut best: Option<(usize, MatchKind)> = None;
+        for (i, (other, other_key)) in candidates {
+            if other.unique || !self.same_length(song, other) {
+                continue;
+            }
+            let kind = match (&song.fingerprint, &other.fingerprint) {
+                (Some(a), Some(b)) => match fingerprint::similar(a, b) {
+                    true if key.is_some() && song.name == other.name => MatchKind::Exact,
+                    true => MatchKind::Fingerprint,
+                    false => continue,
+                },
+                _ => match (key, other_key) {
+                    _ if key.is_none() || other_key.is_none() => continue,
+                    _ if song.name == other.name => MatchKind::Exact,
+                    (Some(key), Some(other_key)) if key == other_key => MatchKind::Normalized,
+                    (Some(key), Some(other_key)) if self.close(key, other_key) => MatchKind::Fuzzy,
+                    _ => continue,
+                },
+            };
+            if kind == MatchKind::Exact {
+                return Some((i, kind));
+            }
+            if best.is_none_or(|(_, k)| kind < k) {
+                best = Some((i, kind));
+            }
+        }
+        best
+    }
+
+    /// Songs of unknown length are assumed to be the same length.
+    fn same_length(&self, a: &Song, b: &Song) -> bool {
+        match (self.config.duration_tolerance, a.duration, b.duration) {
+            (Some(tolerance), Some(a), Some(b)) => (a - b).abs() <= tolerance,
+            _ => true,
+        }
+    }
+
+    fn close(&self, a: &str, b: &str) -> bool {
+        let max = self.config.max_distance;
+        max > 0
+            && a.chars().count().min(b.chars().count()) > max
+            && strsim::levenshtein(a, b) <= max
+    }
+}
+
+/// Whether `word` is `base` or an inflection of it like `remastered` or `versions`.
+fn inflects(word: &str, base: &str) -> bool {
+    word.strip_prefix(base)
+        .is_some_and(|end| ["", "s", "es", "ed"].contains(&end))
+}
+
+/// Lowercase alphanumeric words separated by single spaces.
+fn collapse(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn normalize() {
+        let config = Config::default();
+        let matcher = Matcher::new(&config);
+        let song = matcher.normalize("Song");
+        for title in [
+            "song",
+            "SONG!",
+            "Song (Remastered 2011)",
+            "Song [2011 Remaster]",
+            "Song - 2011 Remaster",
+            "Song - Live",
+            "Song feat. Someone",
+            "Song (feat. Someone)",
+            "Ｓｏｎｇ",
+            "Song (Remastered)",
+            "Song - Remasters",
+            "Song (Live at Wembley)",
+            "Song - Mono Version",
+            "Song [Bonus Track]",
+        ] {
+            assert_eq!(matcher.normalize(title), song, "{title}");
+        }
+        // suffixes are whole words
+        for (title, normalized) in [
+            ("Song - Liverpool", "song liverpool"),
+            ("Song (Lively)", "song lively"),
+            ("Song (Demons)", "song demons"),
+            ("Song - Monologue", "song monologue"),
+            ("Song - Cleaner", "song cleaner"),
+            ("Song (Stereotype)", "song stereotype"),
+            ("Song - Mixtape", "song mixtape"),
+            ("Song (Bonus)", "song bonus"),
+        ] {
+            assert_eq!(matcher.normalize(title), normalized, "{title}");
+        }
+        // only suffixes are stripped
+        assert_eq!(matcher.normalize("Song (Part 2)"), "song part 2");
+        assert_eq!(matcher.normalize("Song - Part 2"), "song part 2");
+        assert_eq!(matcher.normalize("Feat"), "feat");
+        assert_eq!(matcher.normalize("Song (unclosed"), "song unclosed");
+    }
+
+    #[test]
+    fn custom_suffixes() {
+        let mut config = Config::default();
+        config.suffixes.push("Radio Edit".to_string());
+        let matcher = Matcher::new(&config);
+        assert_eq!(matcher.normalize("Song (Radio Edit)"), "song");
+        assert_eq!(matcher.normalize("Song (Radio)"), "song radio");
+    }
+
+    fn song(name: &str, duration: Option<f64>) -> Song {
+        let mut song = Song::new(PathBuf::from(name));
+        song.name = name.to_string();
+        song.duration = duration;
+        song
+    }
+
+    #[test]
+    fn find() {
+        let config = Config {
+            max_distance: 1,
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config);
+        let songs = [
+            song("Songs", Some(100.0)),
+            song("song", Some(100.0)),
+            song("Song", Some(200.0)),
+        ];
+        let keys = matcher.keys(&songs);
+        let find = |s: &Song| {
+            matcher.find(
+                (s, &matcher.keys(std::slice::from_ref(s))[0]),
+                &songs,
+                &keys,
+            )
+        };
+        assert_eq!(
+            find(&song("Song", Some(101.0))),
+            Some((1, MatchKind::Normalized))
+        );
+        assert_eq!(find(&song("Song", None)), Some((2, MatchKind::Exact)));
+        assert_eq!(
+            find(&song("Songz", Some(100.0))),
+            Some((0, MatchKind::Fuzzy))
+        );
+        assert_eq!(find(&song("Other", Some(100.0))), None);
+        assert_eq!(find(&song(MISSING, Some(100.0))), None);
+        let mut unique = song("Song", Some(200.0));
+        unique.unique = true;
+        assert_eq!(find(&unique), None);
+    }
+}
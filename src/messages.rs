@@ -1,28 +1,245 @@
-use crate::song_data::Song;
-use std::{collections::BTreeMap, path::PathBuf};
+use crate::{
+    matching::Overlap,
+    quality::{Format, Keep},
+    song_data::*,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Info {
-    PartialSubset(String, String, Vec<String>),
-    Subset(String, String, PathBuf),
+    PartialSubset(String, String, Vec<Overlap>, f32),
+    Subset(String, String, Option<PathBuf>, Vec<Overlap>),
+    /// Album, the (artist, album)s of other artists that together contain all its songs,
+    /// path and the matched songs.
+    CrossSubset(String, Vec<(Artist, String)>, Option<PathBuf>, Vec<Overlap>),
+    /// Album, other album with the same songs in another format, their formats, which of
+    /// them to keep, path and the matched songs.
+    OtherFormat(
+        String,
+        String,
+        (Format, Format),
+        Keep,
+        Option<PathBuf>,
+        Vec<Overlap>,
+    ),
+    /// A song and the other songs with byte for byte identical audio.
+    IdenticalFile(PathBuf, Vec<PathBuf>),
+    /// What occurs more than once in the album, a title or a track number, and the songs.
+    DuplicateTrack(String, Vec<PathBuf>),
+    /// The albums of the same artist that are subsets of this album, so this album is the
+    /// only one of them worth keeping.
+    Covers(Vec<String>),
+    /// Every missing track or disc found in the album.
+    Incomplete(Vec<String>),
     Empty(PathBuf),
     MissingTitle(Vec<String>),
+    /// Songs without a title tag and the titles parsed from their filenames, see
+    /// `filename::infer`.
+    InferredTitle(Vec<(String, String)>),
+}
+impl Info {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Info::PartialSubset(..) => "Partial subset",
+            Info::Subset(..) => "Subset",
+            Info::CrossSubset(..) => "Cross-artist subset",
+            Info::OtherFormat(..) => "Other format",
+            Info::IdenticalFile(..) => "Identical file",
+            Info::DuplicateTrack(..) => "Duplicate track",
+            Info::Covers(_) => "Covers",
+            Info::Incomplete(_) => "Incomplete",
+            Info::Empty(_) => "Empty",
+            Info::MissingTitle(_) => "Missing titles",
+            Info::InferredTitle(_) => "Titles from filenames",
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Info::PartialSubset(a, b, songs, percent) => format!(
+                "{a:?} is a partial subset of {b:?} ({percent:.0}% of {a:?})\n\n{}",
+                songs
+                    .iter()
+                    .map(|o| match o.via.label() {
+                        Some(via) => format!("- {} ({via}: {:?})", o.song.name, o.other.name),
+                        None => format!("- {}", o.song.name),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+            Info::Subset(a, b, _, songs) => {
+                format!("{a:?} is a subset of {b:?}{}", matched_via(songs))
+            }
+            Info::CrossSubset(a, others, _, songs) => format!(
+                "{a:?} is a subset of {}{}",
+                others
+                    .iter()
+                    .map(|(artist, b)| format!("{b:?} by {artist:?}"))
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+                matched_via(songs)
+            ),
+            Info::OtherFormat(a, b, (format_a, format_b), keep, _, _) => format!(
+                "{a:?} ({format_a}) is the same album as {b:?} ({format_b}), {}",
+                match keep {
+                    Keep::This => format!("keep {a:?}"),
+                    Keep::Other => format!("keep {b:?}"),
+                    Keep::Either => "both are equally good".to_string(),
+                }
+            ),
+            Info::IdenticalFile(path, others) => format!(
+                "{} is identical to\n\n{}",
+                path.display(),
+                others
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            ),
+            Info::DuplicateTrack(what, paths) => format!(
+                "{what} occurs {} times\n\n{}",
+                paths.len(),
+                paths
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            ),
+            Info::Covers(albums) => format!(
+                "{} {} fully covered by this album",
+                albums
+                    .iter()
+                    .map(|a| format!("{a:?}"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                match albums.len() {
+                    1 => "is",
+                    _ => "are",
+                }
+            ),
+            Info::Incomplete(problems) => problems.join("\n"),
+            Info::Empty(_) => "this album contains no songs".to_string(),
+            Info::MissingTitle(titles) => format!("\n{}", titles.join("\n\n")),
+            Info::InferredTitle(songs) => format!(
+                "\n{}",
+                songs
+                    .iter()
+                    .map(|(path, title)| format!("{path} ({title:?})"))
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            ),
+        }
+    }
+
+    /// The album directory that can be removed to resolve this finding.
+    pub fn removable_path(&self) -> Option<&PathBuf> {
+        match self {
+            Info::Subset(_, _, path, _) | Info::CrossSubset(_, _, path, _) => path.as_ref(),
+            Info::OtherFormat(_, _, _, Keep::Other, path, _) => path.as_ref(),
+            Info::Empty(path) => Some(path),
+            Info::PartialSubset(..)
+            | Info::OtherFormat(..)
+            | Info::IdenticalFile(..)
+            | Info::DuplicateTrack(..)
+            | Info::Covers(_)
+            | Info::Incomplete(_)
+            | Info::MissingTitle(_)
+            | Info::InferredTitle(_) => None,
+        }
+    }
+
+    /// The album this finding compares against, if any. For cross-artist subsets this
+    /// is the album containing most of the songs.
+    pub fn other_album(&self) -> Option<&str> {
+        match self {
+            Info::PartialSubset(_, b, _, _)
+            | Info::Subset(_, b, _, _)
+            | Info::OtherFormat(_, b, _, _, _, _) => Some(b),
+            Info::CrossSubset(_, others, _, _) => others.first().map(|(_, b)| b.as_str()),
+            Info::IdenticalFile(..)
+            | Info::DuplicateTrack(..)
+            | Info::Covers(_)
+            | Info::Incomplete(_)
+            | Info::Empty(_)
+            | Info::MissingTitle(_)
+            | Info::InferredTitle(_) => None,
+        }
+    }
+
+    /// The artist of [`Info::other_album`] if it was filed under another artist.
+    pub fn other_artist(&self) -> Option<&str> {
+        match self {
+            Info::CrossSubset(_, others, _, _) => others.first().map(|(a, _)| a.as_str()),
+            _ => None,
+        }
+    }
+
+    /// The songs shared with [`Info::other_album`].
+    pub fn overlapping_songs(&self) -> &[Overlap] {
+        match self {
+            Info::PartialSubset(_, _, songs, _)
+            | Info::Subset(_, _, _, songs)
+            | Info::CrossSubset(_, _, _, songs)
+            | Info::OtherFormat(_, _, _, _, _, songs) => songs,
+            Info::IdenticalFile(..)
+            | Info::DuplicateTrack(..)
+            | Info::Covers(_)
+            | Info::Incomplete(_)
+            | Info::Empty(_)
+            | Info::MissingTitle(_)
+            | Info::InferredTitle(_) => &[],
+        }
+    }
+}
+
+/// Lists how the songs were matched unless they all matched exactly.
+fn matched_via(songs: &[Overlap]) -> String {
+    let mut via = songs
+        .iter()
+        .filter_map(|o| o.via.label())
+        .collect::<BTreeSet<_>>();
+    if songs
+        .iter()
+        .any(|o| o.song.title_inferred || o.other.title_inferred)
+    {
+        via.insert("title from filename");
+    }
+    match via.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", via.into_iter().collect::<Vec<_>>().join(", ")),
+    }
 }
 
 #[derive(Debug)]
 pub enum ClientMessage {
     ArtistLoadingAdd,
-    InfoLoadingDone,
+    SongLoaded,
     InfoLoadingAdd,
     AddArtistPath(String, PathBuf),
-    AddSong(String, String, Song),
-    AddInfo(String, String, Info),
+    /// Replaces everything known about an artist, removing it if it has no albums.
+    SetArtist(String, Albums),
+    /// Replaces every finding of an artist at once.
+    SetInfo(String, BTreeMap<String, Vec<Info>>),
+    /// Replaces every finding that needed the whole library, see `global::analyze`.
+    SetGlobalInfo(BTreeMap<String, BTreeMap<String, Vec<Info>>>),
+    /// A folder that couldn't be read and the error, its songs are missing from the scan.
+    Skipped(PathBuf, String),
 }
 
 pub enum InfoMessage {
-    Analyze(String, BTreeMap<String, (Vec<Song>, PathBuf)>),
+    Analyze(String, Albums),
 }
 
 pub enum WorkMessage {
+    /// Rescans the artist with this folder's name in every root.
     WorkOnFolder(PathBuf),
+    /// Rescans every root, used when songs are grouped by their tags or `--layout` as any
+    /// file can move songs between artists.
+    Rescan,
+    /// Adds another collection and rescans everything.
+    AddRoot(PathBuf),
 }
@@ -0,0 +1,146 @@
+use anyhow::{bail, Result};
+use std::{collections::HashMap, str::FromStr};
+
+/// A template like `{year} - {album}` that names the parts of a string.
+#[derive(Debug, Clone)]
+pub struct Pattern(Vec<Token>);
+
+#[derive(Debug, Clone)]
+enum Token {
+    Text(String),
+    Field(String),
+}
+
+impl Pattern {
+    pub fn has_field(&self, field: &str) -> bool {
+        self.0
+            .iter()
+            .any(|t| matches!(t, Token::Field(f) if f == field))
+    }
+
+    /// The value of every field if `s` matches the pattern. Fields are never empty and
+    /// take as little as possible when the pattern is ambiguous.
+    pub fn captures(&self, s: &str) -> Option<HashMap<String, String>> {
+        let mut captures = HashMap::new();
+        matches(&self.0, s, &mut captures).then_some(captures)
+    }
+}
+
+fn matches(tokens: &[Token], s: &str, captures: &mut HashMap<String, String>) -> bool {
+    match tokens.split_first() {
+        None => s.is_empty(),
+        Some((Token::Text(text), rest)) => s
+            .strip_prefix(text.as_str())
+            .is_some_and(|s| matches(rest, s, captures)),
+        Some((Token::Field(field), rest)) => {
+            for (end, c) in s.char_indices() {
+                let end = end + c.len_utf8();
+                if matches(rest, &s[end..], captures) {
+                    captures.insert(field.clone(), s[..end].trim().to_string());
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => {
+                    let Some(end) = rest.find('}') else {
+                        bail!("unclosed {{ in {s:?}");
+                    };
+                    let field = &rest[1..end];
+                    if field.is_empty() || field.contains('{') {
+                        bail!("invalid field {{{field}}} in {s:?}");
+                    }
+                    if let Some(Token::Field(_)) = tokens.last() {
+                        bail!("{s:?} has two fields without text between them");
+                    }
+                    tokens.push(Token::Field(field.to_string()));
+                    rest = &rest[end + 1..];
+                }
+                Some(start) => {
+                    tokens.push(Token::Text(rest[..start].to_string()));
+                    rest = &rest[start..];
+                }
+                None => {
+                    tokens.push(Token::Text(rest.to_string()));
+                    rest = "";
+                }
+            }
+        }
+        Ok(Pattern(tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captures(pattern: &str, s: &str) -> Option<Vec<(String, String)>> {
+        let pattern = pattern.parse::<Pattern>().unwrap();
+        let mut captures = pattern.captures(s)?.into_iter().collect::<Vec<_>>();
+        captures.sort();
+        Some(captures)
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn fields_and_text() {
+        assert_eq!(
+            captures("{year} - {album}", "1999 - Some Album"),
+            fields(&[("album", "Some Album"), ("year", "1999")])
+        );
+        assert_eq!(captures("{album}", "Album"), fields(&[("album", "Album")]));
+        assert_eq!(captures("CD{disc}", "CD 2"), fields(&[("disc", "2")]));
+        assert_eq!(captures("Live", "Live"), fields(&[]));
+    }
+
+    #[test]
+    fn shortest_match_first() {
+        assert_eq!(
+            captures("{track} - {title}", "01 - Song - Live"),
+            fields(&[("title", "Song - Live"), ("track", "01")])
+        );
+        assert_eq!(
+            captures("{a}.{b}", "ä.ö.ü"),
+            fields(&[("a", "ä"), ("b", "ö.ü")])
+        );
+    }
+
+    #[test]
+    fn no_match() {
+        assert_eq!(captures("{year} - {album}", "Album"), None);
+        assert_eq!(captures("{year} - {album}", "1999 - "), None);
+        assert_eq!(captures("{album} (Live)", "Album (Live) extra"), None);
+        assert_eq!(captures("Live", "Alive"), None);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!("{album".parse::<Pattern>().is_err());
+        assert!("{}".parse::<Pattern>().is_err());
+        assert!("{a{b}".parse::<Pattern>().is_err());
+        assert!("{artist}{album}".parse::<Pattern>().is_err());
+        assert!("{year} - {album}"
+            .parse::<Pattern>()
+            .unwrap()
+            .has_field("album"));
+    }
+}
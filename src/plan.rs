@@ -0,0 +1,379 @@
+use crate::{messages::Info, remove::*, song_data::Artist, InfoTree};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// An album the plan would remove.
+pub struct PlanItem {
+    pub artist: Artist,
+    pub album: String,
+    pub reasons: BTreeSet<String>,
+    pub checked: bool,
+    pub size: u64,
+    /// Whether another album is a subset of this one too, so they have the same songs
+    /// and only one of them is in the plan.
+    pub mutual: bool,
+}
+
+/// Every removable album from the findings, to be reviewed and removed in one go.
+#[derive(Default)]
+pub struct Plan {
+    pub items: BTreeMap<PathBuf, PlanItem>,
+    pub log: Vec<String>,
+    /// Albums removed since their artist was analyzed last, with their artist. They are
+    /// left out of the plan until the findings without them arrive.
+    pub removed: BTreeMap<PathBuf, Artist>,
+}
+impl Plan {
+    /// Adds new removable albums from `info` and drops the ones that are no longer found.
+    /// Of albums that are subsets of each other only one is added, unchecked, the other
+    /// one (the covering album if [`Info::Covers`] picked one) is kept.
+    pub fn refresh(&mut self, info: &InfoTree) {
+        let mut found = BTreeMap::<&PathBuf, Found>::new();
+        let mut covers = BTreeSet::new();
+        for (artist, albums) in info {
+            for (album, fields) in albums {
+                for field in fields {
+                    if let Info::Covers(_) = field {
+                        covers.insert((artist.as_str(), album.as_str()));
+                    }
+                    let Some(path) = field
+                        .removable_path()
+                        .filter(|path| !self.removed.contains_key(*path))
+                    else {
+                        continue;
+                    };
+                    let found = found.entry(path).or_insert_with(|| Found {
+                        album: (artist, album),
+                        reasons: BTreeSet::new(),
+                        of: BTreeSet::new(),
+                    });
+                    found.reasons.insert(field.description());
+                    match field {
+                        Info::Subset(_, other, _, _) => {
+                            found.of.insert((artist.as_str(), other.as_str()));
+                        }
+                        Info::CrossSubset(_, others, _, _) => {
+                            found
+                                .of
+                                .extend(others.iter().map(|(a, b)| (a.as_str(), b.as_str())));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // the albums in the plan that are a subset of an album that is a subset of them
+        let paths = found
+            .iter()
+            .map(|(path, f)| ((f.album.0.as_str(), f.album.1.as_str()), *path))
+            .collect::<BTreeMap<_, _>>();
+        let mut mutual = BTreeMap::new();
+        for (path, f) in &found {
+            let this = (f.album.0.as_str(), f.album.1.as_str());
+            let partners =
+                f.of.iter()
+                    .filter(|other| {
+                        paths
+                            .get(other)
+                            .is_some_and(|p| found[p].of.contains(&this))
+                    })
+                    .map(|other| (covers.contains(other), Reverse(*other)))
+                    .collect::<Vec<_>>();
+            // keeps the best of albums with the same songs, covering albums first
+            let rank = (covers.contains(&this), Reverse(this));
+            if !partners.is_empty() && partners.iter().all(|p| *p < rank) {
+                continue;
+            }
+            mutual.insert(*path, !partners.is_empty());
+        }
+
+        self.items.retain(|path, _| mutual.contains_key(path));
+        for (path, mutual) in mutual {
+            let Found { album, reasons, .. } = found.remove(path).unwrap();
+            let item = self.items.entry(path.clone()).or_insert_with(|| PlanItem {
+                artist: album.0.clone(),
+                album: album.1.clone(),
+                reasons: BTreeSet::new(),
+                checked: !mutual,
+                size: size(path),
+                mutual,
+            });
+            if mutual && !item.mutual {
+                item.checked = false;
+            }
+            item.mutual = mutual;
+            item.reasons = reasons;
+        }
+    }
+
+    /// New findings of `artist` arrived, so its removed albums are no longer in them.
+    pub fn analyzed(&mut self, artist: &Artist) {
+        self.removed.retain(|_, removed| removed != artist);
+    }
+
+    pub fn checked(&self) -> impl Iterator<Item = (&PathBuf, &PlanItem)> {
+        self.items.iter().filter(|(_, item)| item.checked)
+    }
+
+    /// Disk space removing every checked album would free.
+    pub fn total_size(&self) -> u64 {
+        self.checked().map(|(_, item)| item.size).sum()
+    }
+
+    /// A shell script doing what [`Plan::apply`] would do. Quarantined albums get the same
+    /// destination `apply` would give them now, and the script stops instead of moving an
+    /// album into a folder that appeared since.
+    pub fn script(&self, mode: RemoveMode, roots: &[PathBuf], quarantine: &Path) -> String {
+        let mut script = String::from("#!/bin/sh\n# generated by subset-album\nset -e\n\n");
+        if mode == RemoveMode::Quarantine {
+            script.push_str(QUARANTINE);
+        }
+        let mut planned = BTreeSet::new();
+        for (path, item) in self.checked() {
+            for reason in &item.reasons {
+                script.push_str(&format!("# {}\n", reason.replace('\n', " ")));
+            }
+            let line = match mode {
+                RemoveMode::Trash => format!("gio trash -- {}", quote(path)),
+                RemoveMode::Quarantine => {
+                    let to = quarantine_path(path, roots, quarantine, |to| {
+                        to.exists() || planned.contains(to)
+                    });
+                    let line = format!("quarantine {} {}", quote(path), quote(&to));
+                    planned.insert(to);
+                    line
+                }
+                RemoveMode::Delete => format!("rm -rf -- {}", quote(path)),
+            };
+            script.push_str(&line);
+            script.push_str("\n\n");
+        }
+        script
+    }
+
+    /// Removes `albums`, logging what happened to each of them.
+    /// Returns the albums that were removed.
+    pub fn apply(
+        &mut self,
+        albums: &[(Artist, PathBuf)],
+        mode: RemoveMode,
+        roots: &[PathBuf],
+        quarantine: &Path,
+    ) -> Vec<(Artist, Removal)> {
+        let mut removed = Vec::new();
+        for (artist, path) in albums {
+            self.log.push(match remove(path, mode, roots, quarantine) {
+                Ok(removal) => {
+                    self.items.remove(path);
+                    self.removed.insert(path.clone(), artist.clone());
+                    let line = removal.to_string();
+                    removed.push((artist.clone(), removal));
+                    line
+                }
+                Err(e) => format!("error removing {path:?}: {e}"),
+            });
+        }
+        removed
+    }
+}
+
+/// A removable album while refreshing the plan.
+struct Found<'a> {
+    album: (&'a Artist, &'a String),
+    reasons: BTreeSet<String>,
+    /// The (artist, album)s it is a subset of.
+    of: BTreeSet<(&'a str, &'a str)>,
+}
+
+fn size(path: &Path) -> u64 {
+    files(path)
+        .iter()
+        .filter_map(|f| fs::metadata(f).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Moves an album like [`remove`] does, unless its destination exists.
+const QUARANTINE: &str = r#"quarantine() {
+    if [ -e "$2" ]; then
+        echo "$2 exists already, not moving $1" >&2
+        exit 1
+    fi
+    mkdir -p -- "$(dirname -- "$2")"
+    mv -- "$1" "$2"
+}
+
+"#;
+
+/// `path` quoted for the shell. Bytes that aren't UTF-8 are written with `printf`, so the
+/// script still names the right folder.
+fn quote(path: &Path) -> String {
+    let mut quoted = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        if !chunk.valid().is_empty() {
+            quoted.push_str(&format!("'{}'", chunk.valid().replace('\'', r"'\''")));
+        }
+        if !chunk.invalid().is_empty() {
+            let octal = chunk
+                .invalid()
+                .iter()
+                .map(|b| format!("\\{b:03o}"))
+                .collect::<String>();
+            quoted.push_str(&format!("\"$(printf '{octal}')\""));
+        }
+    }
+    match quoted.is_empty() {
+        true => "''".to_string(),
+        false => quoted,
+    }
+}
+
+pub fn human_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if size < 1024.0 {
+            return format!("{size:.1} {unit}");
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.1} TiB")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subset(album: &str, of: &str) -> Info {
+        let path = PathBuf::from(format!("/music/Band/{album}"));
+        Info::Subset(album.to_string(), of.to_string(), Some(path), Vec::new())
+    }
+
+    fn refresh(plan: &mut Plan, found: &[(&str, Vec<Info>)]) -> Vec<(String, bool)> {
+        let albums = found
+            .iter()
+            .map(|(album, info)| (album.to_string(), info.clone()))
+            .collect();
+        plan.refresh(&BTreeMap::from([("Band".to_string(), albums)]));
+        plan.items
+            .values()
+            .map(|item| (item.album.clone(), item.checked))
+            .collect()
+    }
+
+    #[test]
+    fn subsets_of_each_other() {
+        let mut plan = Plan::default();
+        let found = [
+            ("A", vec![subset("A", "B")]),
+            ("B", vec![subset("B", "A")]),
+            ("Single", vec![subset("Single", "A")]),
+        ];
+        // only one copy is in the plan, and it isn't removed unless checked
+        assert_eq!(
+            refresh(&mut plan, &found),
+            vec![("B".to_string(), false), ("Single".to_string(), true)]
+        );
+
+        // the covering album is kept
+        let mut plan = Plan::default();
+        let mut found = found;
+        found[1].1.push(Info::Covers(Vec::new()));
+        assert_eq!(
+            refresh(&mut plan, &found),
+            vec![("A".to_string(), false), ("Single".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn copies_found_later() {
+        let mut plan = Plan::default();
+        assert_eq!(
+            refresh(&mut plan, &[("A", vec![subset("A", "B")])]),
+            vec![("A".to_string(), true)]
+        );
+        let found = [("A", vec![subset("A", "B")]), ("B", vec![subset("B", "A")])];
+        // the album that was checked is the one kept now
+        assert_eq!(refresh(&mut plan, &found), vec![("B".to_string(), false)]);
+        assert!(plan.items.values().all(|item| item.mutual));
+
+        // a checked album that turns out to have the same songs as another is unchecked
+        let mut plan = Plan::default();
+        refresh(&mut plan, &[("A", vec![subset("A", "B")])]);
+        let found = [
+            ("A", vec![subset("A", "B")]),
+            ("B", vec![subset("B", "A"), Info::Covers(Vec::new())]),
+        ];
+        assert_eq!(refresh(&mut plan, &found), vec![("A".to_string(), false)]);
+    }
+
+    #[test]
+    fn removed_albums_stay_out() {
+        let mut plan = Plan::default();
+        let found = [("Single", vec![subset("Single", "A")])];
+        refresh(&mut plan, &found);
+        let path = PathBuf::from("/music/Band/Single");
+        plan.items.remove(&path);
+        plan.removed.insert(path, "Band".to_string());
+        // the findings from before the removal
+        assert_eq!(refresh(&mut plan, &found), vec![]);
+        plan.analyzed(&"Other".to_string());
+        assert_eq!(refresh(&mut plan, &found), vec![]);
+        plan.analyzed(&"Band".to_string());
+        assert_eq!(refresh(&mut plan, &[]), vec![]);
+        assert!(plan.removed.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn script_quarantines_like_apply() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, process::Command};
+        let dir = std::env::temp_dir().join(format!("subset-album-{}-script", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (flac, mp3, quarantine) = (dir.join("flac"), dir.join("mp3"), dir.join("q"));
+        // the same album in two collections, one of them not UTF-8 and quoted
+        let albums = [
+            flac.join("Band").join(OsStr::from_bytes(b"Caf\xe9 'Live'")),
+            mp3.join("Band").join(OsStr::from_bytes(b"Caf\xe9 'Live'")),
+        ];
+        for album in &albums {
+            fs::create_dir_all(album).unwrap();
+            fs::write(album.join("song.mp3"), "").unwrap();
+        }
+        fs::create_dir_all(
+            quarantine
+                .join("Band")
+                .join(OsStr::from_bytes(b"Caf\xe9 'Live'")),
+        )
+        .unwrap();
+
+        let mut plan = Plan::default();
+        for album in &albums {
+            let item = PlanItem {
+                artist: "Band".to_string(),
+                album: "Album".to_string(),
+                reasons: BTreeSet::new(),
+                checked: true,
+                size: 0,
+                mutual: false,
+            };
+            plan.items.insert(album.clone(), item);
+        }
+        let roots = [flac, mp3];
+        let script = plan.script(RemoveMode::Quarantine, &roots, &quarantine);
+        let status = Command::new("sh").arg("-c").arg(&script).status().unwrap();
+        assert!(status.success());
+        for (album, n) in albums.iter().zip([2, 3]) {
+            assert!(!album.exists());
+            let mut name = b"Caf\xe9 'Live'".to_vec();
+            name.extend(format!(" ({n})").as_bytes());
+            let to = quarantine.join("Band").join(OsStr::from_bytes(&name));
+            assert!(to.join("song.mp3").exists(), "{to:?}");
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
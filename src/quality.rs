@@ -0,0 +1,184 @@
+use crate::song_data::Song;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// Used unless `--quality` says otherwise, best first.
+pub const DEFAULT: &str = "flac,alac,wavpack,ape,pcm,mp3:320,aac:256,mp3:245,opus,vorbis,aac,mp3";
+
+const LOSSLESS: &[&str] = &["flac", "alac", "wavpack", "ape", "tta", "pcm"];
+
+/// One entry of the quality preference, a codec and optionally a minimum bitrate, e.g. `mp3:320`.
+#[derive(Debug, Clone)]
+pub struct Quality {
+    codec: String,
+    min_kbps: u32,
+}
+impl FromStr for Quality {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (codec, min_kbps) = match s.split_once(':') {
+            Some((codec, kbps)) => (
+                codec,
+                kbps.trim_end_matches('k')
+                    .parse()
+                    .with_context(|| format!("invalid bitrate in {s:?}"))?,
+            ),
+            None => (s, 0),
+        };
+        Ok(Quality {
+            codec: codec.trim().to_lowercase(),
+            min_kbps,
+        })
+    }
+}
+
+/// Parses a comma separated quality preference, best first.
+pub fn preference(s: &str) -> Result<Vec<Quality>> {
+    s.split(',')
+        .filter(|q| !q.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// The codec and average bitrate most songs of an album have.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Format {
+    pub codec: String,
+    pub kbps: Option<u32>,
+}
+impl Format {
+    pub fn of(songs: &[Song]) -> Option<Format> {
+        let mut codecs = BTreeMap::<&str, Vec<Option<u32>>>::new();
+        for song in songs {
+            if let Some(codec) = &song.codec {
+                codecs.entry(codec).or_default().push(song.bitrate);
+            }
+        }
+        let (codec, bitrates) = codecs.into_iter().max_by_key(|(_, b)| b.len())?;
+        let known = bitrates.iter().flatten().collect::<Vec<_>>();
+        Some(Format {
+            codec: codec.to_string(),
+            kbps: (!known.is_empty())
+                .then(|| known.iter().copied().sum::<u32>() / known.len() as u32),
+        })
+    }
+
+    /// Position in `preference`, lower is better.
+    pub fn rank(&self, preference: &[Quality]) -> usize {
+        preference
+            .iter()
+            .position(|q| {
+                // leave some room for tags and cover art inflating the estimated bitrate
+                q.codec == self.codec
+                    && (q.min_kbps == 0 || self.kbps.unwrap_or_default() * 100 >= q.min_kbps * 95)
+            })
+            .unwrap_or(preference.len())
+    }
+}
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.codec.to_uppercase())?;
+        match self.kbps {
+            Some(kbps) if !LOSSLESS.contains(&self.codec.as_str()) => write!(f, " {kbps}k"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Which of two copies of an album to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Keep {
+    This,
+    Other,
+    Either,
+}
+impl Keep {
+    pub fn compare(this: &Format, other: &Format, preference: &[Quality]) -> Self {
+        match this.rank(preference).cmp(&other.rank(preference)) {
+            std::cmp::Ordering::Less => Keep::This,
+            std::cmp::Ordering::Greater => Keep::Other,
+            std::cmp::Ordering::Equal => Keep::Either,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(codec: &str, kbps: Option<u32>) -> Format {
+        Format {
+            codec: codec.to_string(),
+            kbps,
+        }
+    }
+
+    #[test]
+    fn preferences() {
+        let parsed = preference(" FLAC, mp3:320k,,mp3 ").unwrap();
+        assert_eq!(
+            parsed
+                .iter()
+                .map(|q| (q.codec.as_str(), q.min_kbps))
+                .collect::<Vec<_>>(),
+            vec![("flac", 0), ("mp3", 320), ("mp3", 0)]
+        );
+        assert!(preference("").unwrap().is_empty());
+        for invalid in ["mp3:fast", "flac,mp3:"] {
+            assert!(preference(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn ranks() {
+        let default = preference(DEFAULT).unwrap();
+        for (codec, kbps, rank) in [
+            ("flac", None, 0),
+            ("flac", Some(900), 0),
+            ("mp3", Some(320), 5),
+            // a little below the minimum still counts
+            ("mp3", Some(310), 5),
+            ("mp3", Some(300), 7),
+            ("aac", Some(256), 6),
+            ("opus", Some(96), 8),
+            ("mp3", Some(128), 11),
+            ("mp3", None, 11),
+            ("wma", Some(320), 12),
+        ] {
+            assert_eq!(format(codec, kbps).rank(&default), rank, "{codec} {kbps:?}");
+        }
+    }
+
+    #[test]
+    fn keep() {
+        let default = preference(DEFAULT).unwrap();
+        for (this, other, keep) in [
+            (format("flac", None), format("mp3", Some(320)), Keep::This),
+            (
+                format("mp3", Some(245)),
+                format("mp3", Some(320)),
+                Keep::Other,
+            ),
+            (
+                format("mp3", Some(128)),
+                format("mp3", Some(192)),
+                Keep::Either,
+            ),
+            // codecs missing from the preference are worse than any listed one
+            (
+                format("wma", Some(320)),
+                format("mp3", Some(128)),
+                Keep::Other,
+            ),
+            (format("wma", None), format("tta", None), Keep::Either),
+        ] {
+            assert_eq!(
+                Keep::compare(&this, &other, &default),
+                keep,
+                "{this} {other}"
+            );
+        }
+    }
+}
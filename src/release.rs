@@ -0,0 +1,220 @@
+use crate::{song_data::Song, tag_reader::Tags};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// What kind of release an album is. Later variants win when a release is several at once,
+/// e.g. a live compilation is a compilation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ReleaseType {
+    Album,
+    Single,
+    Ep,
+    Live,
+    Compilation,
+}
+impl FromStr for ReleaseType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "album" => ReleaseType::Album,
+            "single" => ReleaseType::Single,
+            "ep" => ReleaseType::Ep,
+            "live" => ReleaseType::Live,
+            "compilation" => ReleaseType::Compilation,
+            _ => {
+                bail!("unknown release type {s:?}, expected album, single, ep, live or compilation")
+            }
+        })
+    }
+}
+impl fmt::Display for ReleaseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReleaseType::Album => "album",
+            ReleaseType::Single => "single",
+            ReleaseType::Ep => "ep",
+            ReleaseType::Live => "live",
+            ReleaseType::Compilation => "compilation",
+        })
+    }
+}
+
+/// The type a MusicBrainz release type tag like `album; live` describes.
+fn from_tag(value: &str) -> Option<ReleaseType> {
+    value
+        .split([';', '/', ','])
+        .filter_map(|t| t.parse().ok())
+        .max()
+}
+
+/// Guesses the type from an album or folder name like `Title (Single)` or `Wembley - Live`.
+/// Only compilations are recognized anywhere in the name, the other types only in brackets,
+/// after a ` - ` or as its last word, so "Live and Let Die" isn't a live album.
+fn from_name(name: &str) -> Option<ReleaseType> {
+    let name = name.to_lowercase();
+    let split = |text: &str| {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let words = split(&name);
+    let has = |phrase: &str| {
+        let phrase = phrase.split(' ').collect::<Vec<_>>();
+        words.windows(phrase.len()).any(|w| w == phrase)
+    };
+    if ["greatest hits", "best of", "compilation", "anthology"]
+        .iter()
+        .any(|p| has(p))
+    {
+        return Some(ReleaseType::Compilation);
+    }
+
+    let mut qualifiers = words.last().cloned().into_iter().collect::<Vec<_>>();
+    let mut bracketed = String::new();
+    let mut depth = 0u32;
+    for c in name.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth = depth.saturating_sub(1);
+                bracketed.push(' ');
+            }
+            c if depth > 0 => bracketed.push(c),
+            _ => {}
+        }
+    }
+    qualifiers.extend(split(&bracketed));
+    if let Some((_, after)) = name.split_once(" - ").or(name.split_once(" – ")) {
+        qualifiers.extend(split(after));
+    }
+    let has = |word: &str| qualifiers.iter().any(|w| w == word);
+    if has("live") {
+        Some(ReleaseType::Live)
+    } else if has("ep") {
+        Some(ReleaseType::Ep)
+    } else if has("single") {
+        Some(ReleaseType::Single)
+    } else {
+        None
+    }
+}
+
+/// The type the MusicBrainz release type tag of a song describes, if it has one.
+pub fn from_tags(tags: &Tags) -> Option<ReleaseType> {
+    from_tag(tags.field(&[
+        "MusicBrainz Album Type",
+        "RELEASETYPE",
+        "MUSICBRAINZ_ALBUMTYPE",
+    ])?)
+}
+
+/// The type most songs of the album are tagged with, otherwise guessed from the album name
+/// and its folder, an album if nothing hints at anything else.
+pub fn of(album: &str, songs: &[Song]) -> ReleaseType {
+    let mut tagged = BTreeMap::<ReleaseType, usize>::new();
+    for song in songs {
+        if let Some(release_type) = song.release_type {
+            *tagged.entry(release_type).or_default() += 1;
+        }
+    }
+    if let Some((release_type, _)) = tagged.into_iter().max_by_key(|(_, n)| *n) {
+        return release_type;
+    }
+    let folder = songs
+        .first()
+        .and_then(|s| s.path.parent()?.file_name())
+        .map(|n| n.to_string_lossy());
+    from_name(album)
+        .or_else(|| from_name(folder.as_deref()?))
+        .unwrap_or(ReleaseType::Album)
+}
+
+/// Which subsets a rule like `single:album` applies to, an album of the first type that is
+/// a subset of an album of the second type. `*` or a missing second type matches any type.
+#[derive(Debug, Clone, Copy)]
+pub struct SubsetRule {
+    subset: Option<ReleaseType>,
+    of: Option<ReleaseType>,
+}
+impl SubsetRule {
+    pub fn matches(&self, subset: ReleaseType, of: ReleaseType) -> bool {
+        self.subset.is_none_or(|t| t == subset) && self.of.is_none_or(|t| t == of)
+    }
+}
+impl FromStr for SubsetRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let any = |t: &str| match t.trim() {
+            "*" | "" => Ok(None),
+            t => t.parse().map(Some),
+        };
+        let (subset, of) = s.split_once(':').unwrap_or((s, "*"));
+        Ok(SubsetRule {
+            subset: any(subset)?,
+            of: any(of)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ReleaseType::*;
+
+    #[test]
+    fn names() {
+        for (name, release_type) in [
+            ("Title", None),
+            ("Title (Single)", Some(Single)),
+            ("Title - Single", Some(Single)),
+            ("Single Ladies", None),
+            ("Title EP", Some(Ep)),
+            ("The EP Sessions", None),
+            ("Wembley (Live)", Some(Live)),
+            ("Title [Live at Wembley 1986]", Some(Live)),
+            ("Title – Live at Wembley", Some(Live)),
+            ("Live", Some(Live)),
+            ("Live and Let Die", None),
+            ("Live at Wembley", None),
+            ("Alive", None),
+            ("Greatest Hits Live", Some(Compilation)),
+            ("The Best of Title", Some(Compilation)),
+            ("Title (Live EP)", Some(Live)),
+        ] {
+            assert_eq!(from_name(name), release_type, "{name}");
+        }
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(from_tag("album; live"), Some(Live));
+        assert_eq!(from_tag("Single"), Some(Single));
+        assert_eq!(from_tag("album/compilation"), Some(Compilation));
+        assert_eq!(from_tag("soundtrack"), None);
+    }
+
+    #[test]
+    fn subset_rules() {
+        for (rule, subset, of, matches) in [
+            ("single:album", Single, Album, true),
+            ("single:album", Single, Ep, false),
+            ("single:album", Ep, Album, false),
+            (" Single : ALBUM ", Single, Album, true),
+            ("live", Live, Compilation, true),
+            ("live:*", Live, Album, true),
+            ("*:album", Ep, Album, true),
+            (":album", Ep, Live, false),
+            ("*", Compilation, Single, true),
+        ] {
+            let parsed = rule.parse::<SubsetRule>().unwrap();
+            assert_eq!(parsed.matches(subset, of), matches, "{rule} {subset}:{of}");
+        }
+        for invalid in ["song:album", "single:alb", "single;album"] {
+            assert!(invalid.parse::<SubsetRule>().is_err(), "{invalid}");
+        }
+    }
+}
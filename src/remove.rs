@@ -0,0 +1,185 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoveMode {
+    Trash,
+    Quarantine,
+    Delete,
+}
+impl RemoveMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RemoveMode::Trash => "Move to trash",
+            RemoveMode::Quarantine => "Move to quarantine folder",
+            RemoveMode::Delete => "Delete permanently",
+        }
+    }
+}
+
+/// A removed album directory and where it went.
+#[derive(Debug, Clone)]
+pub enum Removal {
+    Trashed(PathBuf),
+    Quarantined { from: PathBuf, to: PathBuf },
+    Deleted(PathBuf),
+}
+impl Removal {
+    /// Where the directory used to be.
+    pub fn path(&self) -> &Path {
+        match self {
+            Removal::Trashed(path) | Removal::Deleted(path) => path,
+            Removal::Quarantined { from, .. } => from,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !matches!(self, Removal::Deleted(_))
+    }
+
+    /// Puts the directory back where it was.
+    pub fn undo(&self) -> Result<()> {
+        if self.path().exists() {
+            bail!("{:?} exists again", self.path());
+        }
+        match self {
+            Removal::Trashed(path) => restore_from_trash(path),
+            Removal::Quarantined { from, to } => move_dir(to, from),
+            Removal::Deleted(path) => bail!("{path:?} was deleted permanently"),
+        }
+    }
+}
+impl std::fmt::Display for Removal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Removal::Trashed(path) => write!(f, "moved {path:?} to the trash"),
+            Removal::Quarantined { from, to } => write!(f, "moved {from:?} to {to:?}"),
+            Removal::Deleted(path) => write!(f, "deleted {path:?}"),
+        }
+    }
+}
+
+/// Removes the album directory `path`. Quarantined albums keep their path
+/// relative to the root they are in inside the `quarantine` directory.
+pub fn remove(
+    path: &Path,
+    mode: RemoveMode,
+    roots: &[PathBuf],
+    quarantine: &Path,
+) -> Result<Removal> {
+    match mode {
+        RemoveMode::Trash => {
+            trash::delete(path)?;
+            Ok(Removal::Trashed(path.to_path_buf()))
+        }
+        RemoveMode::Quarantine => {
+            if quarantine.as_os_str().is_empty() {
+                bail!("no quarantine folder set");
+            }
+            let to = quarantine_path(path, roots, quarantine, |to| to.exists());
+            move_dir(path, &to)?;
+            Ok(Removal::Quarantined {
+                from: path.to_path_buf(),
+                to,
+            })
+        }
+        RemoveMode::Delete => {
+            fs::remove_dir_all(path)?;
+            Ok(Removal::Deleted(path.to_path_buf()))
+        }
+    }
+}
+
+/// Where quarantining `path` moves it, with a ` (n)` suffix if `taken` says the
+/// destination is already in use.
+pub fn quarantine_path(
+    path: &Path,
+    roots: &[PathBuf],
+    quarantine: &Path,
+    taken: impl Fn(&Path) -> bool,
+) -> PathBuf {
+    let relative = relative(path, roots);
+    let mut to = quarantine.join(relative);
+    let mut n = 1;
+    while taken(&to) {
+        n += 1;
+        let mut name = relative.as_os_str().to_owned();
+        name.push(format!(" ({n})"));
+        to = quarantine.join(name);
+    }
+    to
+}
+
+/// `path` relative to the root it is in.
+pub fn relative<'a>(path: &'a Path, roots: &[PathBuf]) -> &'a Path {
+    roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path)
+}
+
+/// Every file inside `path`, for showing what a removal would delete.
+pub fn files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            match entry.path().is_dir() {
+                true => dirs.push(entry.path()),
+                false => files.push(entry.path()),
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            copy_dir(from, to)?;
+            fs::remove_dir_all(from)?;
+            Ok(())
+        }
+        r => Ok(r?),
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        match entry.path().is_dir() {
+            true => copy_dir(&entry.path(), &target)?,
+            false => _ = fs::copy(entry.path(), target)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn restore_from_trash(path: &Path) -> Result<()> {
+    let item = trash::os_limited::list()?
+        .into_iter()
+        .filter(|i| i.original_path() == path)
+        .max_by_key(|i| i.time_deleted)
+        .context("couldn't find the album in the trash")?;
+    trash::os_limited::restore_all([item])?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn restore_from_trash(path: &Path) -> Result<()> {
+    bail!("restoring {path:?} from the trash isn't supported on this platform")
+}
@@ -0,0 +1,63 @@
+use crate::{global, messages::*, Artists, InfoTree};
+use std::sync::mpsc::Receiver;
+
+/// Collects every finding and album from the workers until they all hang up. Also returns
+/// how many folders couldn't be read.
+pub fn collect(reciever: Receiver<ClientMessage>) -> (InfoTree, Artists, usize) {
+    let mut info = InfoTree::new();
+    let mut library = Artists::new();
+    let mut global = InfoTree::new();
+    let mut songs = 0;
+    let mut skipped = 0;
+    while let Ok(m) = reciever.recv() {
+        match m {
+            ClientMessage::SongLoaded => songs += 1,
+            ClientMessage::SetInfo(artist, found) => match found.is_empty() {
+                true => _ = info.remove(&artist),
+                false => _ = info.insert(artist, found),
+            },
+            ClientMessage::SetArtist(artist, albums) => match albums.is_empty() {
+                true => _ = library.remove(&artist),
+                false => _ = library.insert(artist, albums),
+            },
+            ClientMessage::SetGlobalInfo(found) => global = found,
+            ClientMessage::Skipped(path, e) => {
+                eprintln!("Error reading {path:?}, skipping it: {e}");
+                skipped += 1;
+            }
+            ClientMessage::ArtistLoadingAdd
+            | ClientMessage::InfoLoadingAdd
+            | ClientMessage::AddArtistPath(..) => {}
+        }
+    }
+    eprintln!("scanned {songs} songs");
+    (global::merge(&info, &global), library, skipped)
+}
+
+/// Prints every finding per artist/album. Returns `true` if any problems were found.
+pub fn print(info: &InfoTree) -> bool {
+    for (artist, albums) in info {
+        println!("{artist}");
+        for (album, fields) in albums {
+            println!("  {album}");
+            for field in fields {
+                let description = field.description();
+                let description = description
+                    .lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n      ");
+                println!("    [{}] {description}", field.kind());
+            }
+        }
+    }
+    // a summary of other findings isn't a problem of its own
+    let problems = info
+        .values()
+        .flat_map(|a| a.values())
+        .flatten()
+        .filter(|f| !matches!(f, Info::Covers(_)))
+        .count();
+    eprintln!("found {problems} potential problems");
+    problems > 0
+}
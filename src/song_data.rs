@@ -1,15 +1,63 @@
+use crate::{fingerprint::Fingerprint, release::ReleaseType};
+use serde::Serialize;
 use std::{cmp::Ordering, collections::BTreeMap, path::PathBuf};
 
 pub type Artists = BTreeMap<Artist, Albums>;
 pub type Albums = BTreeMap<String, Album>;
 pub type Artist = String;
-pub type Album = (Vec<Song>, PathBuf);
+/// The songs of an album and the folder holding only this album, which is `None` when
+/// the album shares its folder with other albums and can't be removed on its own.
+pub type Album = (Vec<Song>, Option<PathBuf>);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Song {
     pub name: String,
+    /// The title was parsed from the filename because the song has no title tag.
+    pub title_inferred: bool,
     pub path: PathBuf,
     pub unique: bool,
+    /// The track artist tag, which may differ from the artist folder.
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<u16>,
+    pub total_tracks: Option<u16>,
+    /// The disc number tag, or the number of the `CD1`/`Disc 2` folder the song is in.
+    pub disc: Option<u16>,
+    pub total_discs: Option<u16>,
+    /// The MusicBrainz release type tag, see `release::of` for the type of a whole album.
+    pub release_type: Option<ReleaseType>,
+    /// Length in seconds, if it could be determined.
+    pub duration: Option<f64>,
+    /// Short codec name, see `audio::properties`.
+    pub codec: Option<String>,
+    /// Average bitrate in kbps, estimated from the file size.
+    pub bitrate: Option<u32>,
+    #[serde(skip)]
+    pub fingerprint: Option<Fingerprint>,
+}
+impl Song {
+    /// A song whose tags haven't been read yet.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            name: MISSING.to_string(),
+            title_inferred: false,
+            path,
+            unique: false,
+            artist: None,
+            album_artist: None,
+            album: None,
+            track: None,
+            total_tracks: None,
+            disc: None,
+            total_discs: None,
+            release_type: None,
+            duration: None,
+            codec: None,
+            bitrate: None,
+            fingerprint: None,
+        }
+    }
 }
 impl PartialEq for Song {
     fn eq(&self, other: &Self) -> bool {
@@ -19,14 +67,14 @@ impl PartialEq for Song {
 impl Eq for Song {}
 impl PartialOrd for Song {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.name.cmp(&other.name))
+        Some(self.cmp(other))
     }
 }
 impl Ord for Song {
     fn cmp(&self, other: &Self) -> Ordering {
         match self.unique || other.unique {
             true => Ordering::Equal,
-            false => other.partial_cmp(other).unwrap(),
+            false => self.name.cmp(&other.name),
         }
     }
 }
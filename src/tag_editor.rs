@@ -0,0 +1,132 @@
+use crate::{filename, song_data::Artist};
+use anyhow::{bail, Context, Result};
+use audiotags::{AudioTag, Id3v2Tag, Tag};
+use std::path::{Path, PathBuf};
+
+/// Used for deriving tags from filenames unless the user enters another pattern.
+pub const DEFAULT_PATTERN: &str = "{track} - {title}";
+
+/// The tags the editor can change, as entered by the user.
+#[derive(Default, Clone)]
+pub struct Tags {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub track: String,
+}
+
+/// A song whose tags are being edited, opened from a missing or inferred titles finding.
+pub struct TagEditor {
+    /// Whose findings to refresh after saving.
+    pub artist: Artist,
+    pub path: PathBuf,
+    /// Every song of the finding, for filling all their titles at once.
+    pub missing: Vec<PathBuf>,
+    pub tags: Tags,
+    pub pattern: String,
+    pub status: Option<String>,
+}
+impl TagEditor {
+    pub fn open(artist: Artist, path: PathBuf, missing: Vec<PathBuf>) -> Self {
+        let tag = Tag::new().read_from_path(&path).ok();
+        let text = |v: Option<&str>| v.unwrap_or_default().to_string();
+        let tags = match &tag {
+            Some(tag) => Tags {
+                title: text(tag.title()),
+                artist: text(tag.artist()),
+                album: text(tag.album_title()),
+                track: tag
+                    .track_number()
+                    .map(|t| t.to_string())
+                    .unwrap_or_default(),
+            },
+            None => Tags::default(),
+        };
+        TagEditor {
+            artist,
+            path,
+            missing,
+            tags,
+            pattern: DEFAULT_PATTERN.to_string(),
+            status: None,
+        }
+    }
+
+    /// Fills the fields named in the pattern from the filename of the song.
+    pub fn fill_from_filename(&mut self) -> Result<()> {
+        let fields = filename::fields(&self.pattern.parse()?, &self.path)?;
+        for (field, value) in fields {
+            match field.as_str() {
+                "title" => self.tags.title = value,
+                "artist" => self.tags.artist = value,
+                "album" => self.tags.album = value,
+                "track" => self.tags.track = value,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the title (and track number if the pattern has one) parsed from the filename
+    /// of every song in [`TagEditor::missing`]. Returns how many songs were tagged.
+    pub fn fill_all_from_filenames(&self) -> Result<usize> {
+        let pattern = self.pattern.parse()?;
+        let mut tagged = 0;
+        for path in &self.missing {
+            let Ok(fields) = filename::fields(&pattern, path) else {
+                continue;
+            };
+            let Some(title) = fields.get("title") else {
+                bail!("the pattern {:?} has no {{title}}", self.pattern);
+            };
+            let mut tag = open(path)?;
+            tag.set_title(title);
+            if let Some(track) = fields.get("track").and_then(|t| t.parse().ok()) {
+                tag.set_track_number(track);
+            }
+            tag.write_to_path(&path.to_string_lossy())
+                .with_context(|| format!("writing tags of {path:?}"))?;
+            tagged += 1;
+        }
+        Ok(tagged)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut tag = open(&self.path)?;
+        let track = match self.tags.track.trim() {
+            "" => None,
+            track => Some(track.parse().context("the track number must be a number")?),
+        };
+        match self.tags.title.trim() {
+            "" => tag.remove_title(),
+            title => tag.set_title(title),
+        }
+        match self.tags.artist.trim() {
+            "" => tag.remove_artist(),
+            artist => tag.set_artist(artist),
+        }
+        match self.tags.album.trim() {
+            "" => tag.remove_album_title(),
+            album => tag.set_album_title(album),
+        }
+        match track {
+            Some(track) => tag.set_track_number(track),
+            None => tag.remove_track_number(),
+        }
+        tag.write_to_path(&self.path.to_string_lossy())?;
+        Ok(())
+    }
+}
+
+type DynTag = Box<dyn AudioTag + Send + Sync>;
+
+/// The tags of the file at `path`, or new ones for MP3s that have none yet.
+fn open(path: &Path) -> Result<DynTag> {
+    match Tag::new().read_from_path(path) {
+        Ok(tag) => Ok(tag),
+        Err(audiotags::Error::Id3TagError(e)) if matches!(e.kind, id3::ErrorKind::NoTag) => {
+            Ok(Box::new(Id3v2Tag::new()))
+        }
+        Err(e) => Err(e).with_context(|| format!("reading tags of {path:?}")),
+    }
+}
@@ -0,0 +1,480 @@
+use crate::container;
+use anyhow::{bail, Context, Result};
+use audiotags::{AudioTag, FlacTag, Id3v2Tag, Mp4Tag, TagType};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// The tags of a song, whichever format they were stored in.
+#[derive(Debug, Default)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub comment: Option<String>,
+    pub track: Option<u16>,
+    pub total_tracks: Option<u16>,
+    pub disc: Option<u16>,
+    pub total_discs: Option<u16>,
+    /// Every text field by its upper case name, including custom ones like ID3 `TXXX`
+    /// frames, Vorbis comments, APE items and MP4 freeform atoms.
+    pub fields: HashMap<String, String>,
+}
+impl Tags {
+    /// The first of the fields `names` that is set, ignoring case.
+    pub fn field(&self, names: &[&str]) -> Option<&str> {
+        names
+            .iter()
+            .find_map(|name| self.fields.get(&name.to_uppercase()))
+            .map(String::as_str)
+    }
+
+    /// Tags from Vorbis comment or APE style fields like `TITLE` and `TRACKNUMBER=3/12`.
+    fn from_fields(fields: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut tags = Tags::default();
+        for (name, value) in fields {
+            tags.fields
+                .entry(name.to_uppercase())
+                .or_insert(value.trim_end_matches('\0').to_string());
+        }
+        let text = |names: &[&str]| tags.field(names).map(str::to_string);
+        let (track, total_tracks) = number(tags.field(&["TRACKNUMBER", "TRACK"]));
+        let (disc, total_discs) = number(tags.field(&["DISCNUMBER", "DISC"]));
+        let total = |names: &[&str]| tags.field(names).and_then(|n| n.trim().parse().ok());
+        Tags {
+            title: text(&["TITLE"]),
+            artist: text(&["ARTIST"]),
+            album_artist: text(&["ALBUMARTIST", "ALBUM ARTIST", "ALBUM_ARTIST"]),
+            album: text(&["ALBUM"]),
+            comment: text(&["COMMENT", "DESCRIPTION"]),
+            track,
+            total_tracks: total_tracks.or_else(|| total(&["TRACKTOTAL", "TOTALTRACKS"])),
+            disc,
+            total_discs: total_discs.or_else(|| total(&["DISCTOTAL", "TOTALDISCS"])),
+            ..tags
+        }
+    }
+}
+
+/// Splits numbers like `3/12` into the number and the total.
+fn number(value: Option<&str>) -> (Option<u16>, Option<u16>) {
+    let Some(value) = value else {
+        return (None, None);
+    };
+    let (n, total) = value.split_once('/').unwrap_or((value, ""));
+    (n.trim().parse().ok(), total.trim().parse().ok())
+}
+
+/// Reads the tags of one kind of container.
+pub trait TagReader: Sync {
+    /// Whether a file starting with `header` is in a container this reader understands.
+    fn sniff(&self, header: &[u8]) -> bool;
+    fn read(&self, path: &Path, header: &[u8]) -> Result<Tags>;
+}
+
+/// Every reader, the first one whose [`TagReader::sniff`] matches reads the tags.
+const READERS: &[&dyn TagReader] = &[&AudioTags, &Ogg, &Ape, &Riff];
+
+/// The tags of the file at `path`, going by its contents rather than its extension.
+pub fn read(path: &Path) -> Result<Tags> {
+    let mut header = Vec::new();
+    File::open(path)?.take(64).read_to_end(&mut header)?;
+    match READERS.iter().find(|r| r.sniff(&header)) {
+        Some(reader) => reader.read(path, &header),
+        None => bail!("unknown container"),
+    }
+}
+
+/// MP3, FLAC and MPEG-4 files through `audiotags`.
+struct AudioTags;
+impl AudioTags {
+    fn tag_type(header: &[u8]) -> Option<TagType> {
+        if header.starts_with(b"ID3")
+            || (header.len() > 1 && header[0] == 0xff && header[1] >= 0xe0)
+        {
+            Some(TagType::Id3v2)
+        } else if header.starts_with(b"fLaC") {
+            Some(TagType::Flac)
+        } else if header.get(4..8) == Some(b"ftyp") {
+            Some(TagType::Mp4)
+        } else {
+            None
+        }
+    }
+}
+impl TagReader for AudioTags {
+    fn sniff(&self, header: &[u8]) -> bool {
+        AudioTags::tag_type(header).is_some()
+    }
+
+    fn read(&self, path: &Path, header: &[u8]) -> Result<Tags> {
+        let tag_type = AudioTags::tag_type(header).unwrap();
+        // `Tag::with_tag_type` still insists on a known extension, so the concrete tags
+        // are used directly
+        let tag: Box<dyn AudioTag> = match tag_type {
+            TagType::Id3v2 => Box::new(Id3v2Tag::read_from_path(path)?),
+            TagType::Flac => Box::new(FlacTag::read_from_path(path)?),
+            TagType::Mp4 => Box::new(Mp4Tag::read_from_path(path)?),
+        };
+        let text = |v: Option<&str>| v.map(str::to_string);
+        Ok(Tags {
+            title: text(tag.title()),
+            artist: text(tag.artist()),
+            album_artist: text(tag.album_artist()),
+            album: text(tag.album_title()),
+            comment: text(tag.comment()),
+            track: tag.track_number(),
+            total_tracks: tag.total_tracks(),
+            disc: tag.disc_number(),
+            total_discs: tag.total_discs(),
+            fields: custom_fields(path, tag_type).unwrap_or_default(),
+        })
+    }
+}
+
+/// The custom fields `audiotags` doesn't expose, ID3 `TXXX` frames, FLAC Vorbis comments
+/// and MP4 `com.apple.iTunes` freeform atoms.
+fn custom_fields(path: &Path, tag_type: TagType) -> Result<HashMap<String, String>> {
+    Ok(match tag_type {
+        TagType::Id3v2 => id3::Tag::read_from_path(path)?
+            .extended_texts()
+            .map(|t| (t.description.to_uppercase(), t.value.clone()))
+            .collect(),
+        TagType::Flac => metaflac::Tag::read_from_path(path)?
+            .vorbis_comments()
+            .map(|c| &c.comments)
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, values)| Some((name.to_uppercase(), values.first()?.clone())))
+            .collect(),
+        TagType::Mp4 => mp4ameta::Tag::read_from_path(path)?
+            .data()
+            .filter_map(|(ident, data)| match ident {
+                mp4ameta::DataIdent::Freeform { mean, name } if mean == "com.apple.iTunes" => {
+                    Some((name.to_uppercase(), data.string()?.to_string()))
+                }
+                _ => None,
+            })
+            .collect(),
+    })
+}
+
+/// Vorbis comments of Ogg Vorbis and Opus files.
+struct Ogg;
+impl TagReader for Ogg {
+    fn sniff(&self, header: &[u8]) -> bool {
+        header.starts_with(b"OggS")
+    }
+
+    fn read(&self, path: &Path, _: &[u8]) -> Result<Tags> {
+        Ok(Tags::from_fields(ogg_comments(&mut File::open(path)?)?))
+    }
+}
+
+/// The Vorbis comments of an Ogg Vorbis or Opus stream.
+fn ogg_comments(stream: &mut impl Read) -> Result<Vec<(String, String)>> {
+    // the comments are the second packet of the stream
+    let packets = ogg_packets(stream, 2)?;
+    let comments = match packets[1].strip_prefix(b"\x03vorbis") {
+        Some(comments) => comments,
+        None => match packets[1].strip_prefix(b"OpusTags") {
+            Some(comments) => comments,
+            None => bail!("no Vorbis or Opus comments"),
+        },
+    };
+    vorbis_comments(comments)
+}
+
+/// The first `count` packets of an Ogg stream.
+fn ogg_packets(file: &mut impl Read, count: usize) -> Result<Vec<Vec<u8>>> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    while packets.len() < count {
+        for len in container::ogg_page(file)? {
+            let start = packet.len();
+            packet.resize(start + len as usize, 0);
+            file.read_exact(&mut packet[start..])?;
+            if container::ends_packet(len) {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+    }
+    Ok(packets)
+}
+
+fn vorbis_comments(mut data: &[u8]) -> Result<Vec<(String, String)>> {
+    let vendor = read_u32(&mut data)?;
+    data = data.get(vendor..).context("truncated Vorbis comments")?;
+    let mut comments = Vec::new();
+    for _ in 0..read_u32(&mut data)? {
+        let len = read_u32(&mut data)?;
+        let comment = data.get(..len).context("truncated Vorbis comments")?;
+        if let Some((name, value)) = String::from_utf8_lossy(comment).split_once('=') {
+            comments.push((name.to_string(), value.to_string()));
+        }
+        data = &data[len..];
+    }
+    Ok(comments)
+}
+
+fn read_u32(data: &mut &[u8]) -> Result<usize> {
+    let mut n = [0; 4];
+    data.read_exact(&mut n)?;
+    Ok(u32::from_le_bytes(n) as usize)
+}
+
+/// APEv2 tags at the end of WavPack and Monkey's Audio files.
+struct Ape;
+impl TagReader for Ape {
+    fn sniff(&self, header: &[u8]) -> bool {
+        header.starts_with(b"wvpk") || header.starts_with(b"MAC ")
+    }
+
+    fn read(&self, path: &Path, _: &[u8]) -> Result<Tags> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Tags::from_fields(ape_items(&mut file, len)?))
+    }
+}
+
+/// The text items of the APEv2 tag at the end of a file of `len` bytes, before an ID3v1 tag
+/// if there is one.
+fn ape_items(file: &mut (impl Read + Seek), len: u64) -> Result<Vec<(String, String)>> {
+    let end = container::before_id3v1(file, len)?;
+    let Some(tag) = container::ape_tag(file, end)? else {
+        bail!("no APE tag");
+    };
+    let (start, end) = tag.items;
+    let mut items = vec![0; (end - start) as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut items)?;
+
+    let mut fields = Vec::new();
+    let mut rest = items.as_slice();
+    for _ in 0..tag.count {
+        if rest.len() < 8 {
+            break;
+        }
+        let len = u32::from_le_bytes(rest[0..4].try_into()?) as usize;
+        let flags = u32::from_le_bytes(rest[4..8].try_into()?);
+        let Some(key_len) = rest[8..].iter().position(|b| *b == 0) else {
+            break;
+        };
+        let key = String::from_utf8_lossy(&rest[8..8 + key_len]).to_string();
+        let start = 8 + key_len + 1;
+        let Some(value) = rest.get(start..start + len) else {
+            break;
+        };
+        // bits 1 and 2 are 0 for UTF-8 text, the others are binary or links
+        if flags & 0b110 == 0 {
+            fields.push((key, String::from_utf8_lossy(value).to_string()));
+        }
+        rest = &rest[start + len..];
+    }
+    Ok(fields)
+}
+
+/// The `LIST INFO` chunk of WAV files.
+struct Riff;
+impl TagReader for Riff {
+    fn sniff(&self, header: &[u8]) -> bool {
+        header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WAVE")
+    }
+
+    fn read(&self, path: &Path, _: &[u8]) -> Result<Tags> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Tags::from_fields(riff_fields(&mut file, len)?))
+    }
+}
+
+/// The fields of the `LIST INFO` chunk of a WAV file of `len` bytes.
+fn riff_fields(file: &mut (impl Read + Seek), len: u64) -> Result<Vec<(String, String)>> {
+    for chunk in container::riff_chunks(file, 12, len)? {
+        if &chunk.id == b"LIST" && chunk.size >= 4 {
+            let mut list = vec![0; chunk.size.min(len - chunk.start) as usize];
+            file.seek(SeekFrom::Start(chunk.start))?;
+            file.read_exact(&mut list)?;
+            if let Some(info) = list.strip_prefix(b"INFO") {
+                return riff_info(info);
+            }
+        }
+    }
+    Ok(Vec::new())
+}
+
+fn riff_info(data: &[u8]) -> Result<Vec<(String, String)>> {
+    let mut fields = Vec::new();
+    for chunk in container::riff_chunks(&mut Cursor::new(data), 0, data.len() as u64)? {
+        let start = chunk.start as usize;
+        let Some(value) = data.get(start..start + chunk.size as usize) else {
+            break;
+        };
+        let value = String::from_utf8_lossy(value)
+            .trim_end_matches('\0')
+            .to_string();
+        let id = String::from_utf8_lossy(&chunk.id).to_string();
+        let name = match id.as_str() {
+            "INAM" => "TITLE",
+            "IART" => "ARTIST",
+            "IPRD" => "ALBUM",
+            "ICMT" => "COMMENT",
+            "ITRK" | "IPRT" => "TRACKNUMBER",
+            "IGNR" => "GENRE",
+            _ => &id,
+        };
+        fields.push((name.to_string(), value));
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::tests::{ape, chunk, page};
+
+    /// A length prefixed string as used by Vorbis comments.
+    fn string(s: &[u8]) -> Vec<u8> {
+        let mut data = (s.len() as u32).to_le_bytes().to_vec();
+        data.extend(s);
+        data
+    }
+
+    fn comments(fields: &[&str]) -> Vec<u8> {
+        let mut data = string(b"vendor");
+        data.extend((fields.len() as u32).to_le_bytes());
+        for field in fields {
+            data.extend(string(field.as_bytes()));
+        }
+        data
+    }
+
+    /// The lacing segments of a packet, 255 bytes each and a shorter one ending it.
+    fn segments(packet: &[u8]) -> Vec<&[u8]> {
+        let mut segments = packet.chunks(255).collect::<Vec<_>>();
+        if packet.len().is_multiple_of(255) {
+            segments.push(&[]);
+        }
+        segments
+    }
+
+    fn ogg(second: &[u8]) -> Cursor<Vec<u8>> {
+        let mut stream = page(&segments(b"\x01vorbis header"));
+        stream.extend(page(&segments(second)));
+        stream.extend(page(&[b"audio"]));
+        Cursor::new(stream)
+    }
+
+    #[test]
+    fn vorbis_comments_of_ogg() {
+        let mut packet = b"\x03vorbis".to_vec();
+        packet.extend(comments(&["TITLE=One", "tracknumber=3/12", "ARTIST=A=B"]));
+        let tags = Tags::from_fields(ogg_comments(&mut ogg(&packet)).unwrap());
+        assert_eq!(tags.title.as_deref(), Some("One"));
+        assert_eq!(tags.artist.as_deref(), Some("A=B"));
+        assert_eq!((tags.track, tags.total_tracks), (Some(3), Some(12)));
+    }
+
+    #[test]
+    fn opus_tags() {
+        let mut packet = b"OpusTags".to_vec();
+        packet.extend(comments(&["TITLE=Two", "TRACKNUMBER=2", "TRACKTOTAL=5"]));
+        let tags = Tags::from_fields(ogg_comments(&mut ogg(&packet)).unwrap());
+        assert_eq!(tags.title.as_deref(), Some("Two"));
+        assert_eq!((tags.track, tags.total_tracks), (Some(2), Some(5)));
+    }
+
+    #[test]
+    fn packets_continue_across_pages() {
+        let title = format!("TITLE={}", "x".repeat(600));
+        let mut packet = b"\x03vorbis".to_vec();
+        packet.extend(comments(&[&title]));
+        let split = segments(&packet);
+        let mut stream = page(&segments(b"\x01vorbis header"));
+        // the first page ends with a full segment, so the packet goes on in the next one
+        stream.extend(page(&split[..2]));
+        stream.extend(page(&split[2..]));
+        let packets = ogg_packets(&mut Cursor::new(stream), 2).unwrap();
+        assert_eq!(packets[1], packet);
+        let fields = vorbis_comments(&packets[1][7..]).unwrap();
+        assert_eq!(fields[0].1.len(), 600);
+    }
+
+    #[test]
+    fn invalid_ogg() {
+        assert!(ogg_comments(&mut ogg(b"\x05vorbis setup")).is_err());
+        assert!(ogg_packets(&mut Cursor::new(b"OggX".repeat(10)), 1).is_err());
+        // claims a comment that isn't there
+        let mut packet = string(b"vendor");
+        packet.extend(1u32.to_le_bytes());
+        assert!(vorbis_comments(&packet).is_err());
+    }
+
+    #[test]
+    fn ape_items_before_id3v1() {
+        let mut file = b"wvpk audio".to_vec();
+        file.extend(ape(&[
+            ("Title", "One", 0),
+            ("Cover Art (Front)", "\x00\x01", 2),
+            ("Track", "1/3", 0),
+        ]));
+        file.extend(b"TAG");
+        file.extend([0; 125]);
+        let len = file.len() as u64;
+        let tags = Tags::from_fields(ape_items(&mut Cursor::new(file), len).unwrap());
+        assert_eq!(tags.title.as_deref(), Some("One"));
+        assert_eq!((tags.track, tags.total_tracks), (Some(1), Some(3)));
+        assert!(tags.field(&["Cover Art (Front)"]).is_none());
+    }
+
+    #[test]
+    fn missing_ape_tag() {
+        let file = vec![0; 200];
+        assert!(ape_items(&mut Cursor::new(file), 200).is_err());
+    }
+
+    #[test]
+    fn riff_info_fields() {
+        let mut info = b"INFO".to_vec();
+        info.extend(chunk(b"INAM", b"Two\0"));
+        info.extend(chunk(b"IART", b"Band\0"));
+        info.extend(chunk(b"ITRK", b"2"));
+        let mut chunks = chunk(b"fmt ", &[1, 2, 3]);
+        chunks.extend(chunk(b"data", &[0; 11]));
+        chunks.extend(chunk(b"LIST", &info));
+        let mut file = b"RIFF".to_vec();
+        file.extend((chunks.len() as u32 + 4).to_le_bytes());
+        file.extend(b"WAVE");
+        file.extend(chunks);
+        assert!(Riff.sniff(&file));
+        let len = file.len() as u64;
+        let tags = Tags::from_fields(riff_fields(&mut Cursor::new(file), len).unwrap());
+        assert_eq!(tags.title.as_deref(), Some("Two"));
+        assert_eq!(tags.artist.as_deref(), Some("Band"));
+        assert_eq!(tags.track, Some(2));
+    }
+
+    #[test]
+    fn sniffing() {
+        let reader = |header: &[u8]| {
+            READERS
+                .iter()
+                .position(|r| r.sniff(header))
+                .map(|i| ["audiotags", "ogg", "ape", "riff"][i])
+        };
+        assert_eq!(reader(b"ID3\x04\x00"), Some("audiotags"));
+        assert_eq!(reader(&[0xff, 0xfb, 0x90]), Some("audiotags"));
+        assert_eq!(reader(b"fLaC\x00"), Some("audiotags"));
+        assert_eq!(reader(b"\x00\x00\x00\x20ftypM4A "), Some("audiotags"));
+        assert_eq!(reader(b"OggS\x00\x02"), Some("ogg"));
+        assert_eq!(reader(b"wvpk"), Some("ape"));
+        assert_eq!(reader(b"MAC \x96\x0f"), Some("ape"));
+        assert_eq!(reader(b"RIFF\x00\x00\x00\x00WAVE"), Some("riff"));
+        assert_eq!(reader(b"RIFF\x00\x00\x00\x00AVI "), None);
+        assert_eq!(reader(b"plain text"), None);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::tag_reader::Tags;
+use anyhow::{bail, Result};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+/// Marks songs below its folder as unique without touching their tags. An empty file marks
+/// every song, otherwise it lists the paths of unique songs relative to its folder.
+pub const SIDECAR: &str = ".subset-ignore";
+
+/// How a song's tags mark it as unique, set with `--unique-marker`.
+#[derive(Debug, Clone)]
+pub enum Marker {
+    /// The comment tag is exactly this text.
+    Comment(String),
+    /// A custom field with this name is set, see [`Tags::fields`].
+    Field(String),
+}
+impl Default for Marker {
+    fn default() -> Self {
+        Marker::Comment("unique".to_string())
+    }
+}
+impl FromStr for Marker {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.split_once(':') {
+            Some(("comment", text)) => Marker::Comment(text.to_string()),
+            Some(("field", name)) if !name.is_empty() => Marker::Field(name.to_string()),
+            _ => bail!("invalid unique marker {s:?}, expected comment:<text> or field:<name>"),
+        })
+    }
+}
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Marker::Comment(text) => write!(f, "comment:{text}"),
+            Marker::Field(name) => write!(f, "field:{name}"),
+        }
+    }
+}
+impl Marker {
+    pub fn is_set(&self, tags: &Tags) -> bool {
+        match self {
+            Marker::Comment(text) => tags.comment.as_deref() == Some(text.as_str()),
+            Marker::Field(name) => tags
+                .field(&[name])
+                .is_some_and(|v| !v.is_empty() && v != "0" && !v.eq_ignore_ascii_case("false")),
+        }
+    }
+}
+
+/// The sidecar files of a scan, each read once no matter how many songs are below it.
+#[derive(Default)]
+pub struct Sidecars(Mutex<HashMap<PathBuf, Option<Sidecar>>>);
+impl Sidecars {
+    /// Whether a sidecar file in the folder of `song` or any folder above it, up to its
+    /// root, marks it as unique.
+    pub fn marks(&self, song: &Path, roots: &[PathBuf]) -> bool {
+        let mut sidecars = self.0.lock().unwrap();
+        for dir in song.ancestors().skip(1) {
+            let sidecar = sidecars
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| fs::read_to_string(dir.join(SIDECAR)).ok().map(parse));
+            let relative = song.strip_prefix(dir).unwrap_or(song);
+            match sidecar {
+                Some(Sidecar::All) => return true,
+                Some(Sidecar::Listed(listed)) if listed.contains(relative) => return true,
+                _ => {}
+            }
+            if roots.iter().any(|root| root == dir) {
+                break;
+            }
+        }
+        false
+    }
+}
+
+enum Sidecar {
+    All,
+    Listed(HashSet<PathBuf>),
+}
+
+fn parse(listed: String) -> Sidecar {
+    let lines = listed
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(PathBuf::from)
+        .collect::<HashSet<_>>();
+    match lines.is_empty() {
+        true => Sidecar::All,
+        false => Sidecar::Listed(lines),
+    }
+}
+
+/// Marks every song below `dir` as unique.
+pub fn mark_folder(dir: &Path) -> Result<()> {
+    fs::write(dir.join(SIDECAR), "")?;
+    Ok(())
+}
+
+/// Marks `songs` as unique by adding them to the sidecar files of their folders.
+pub fn mark(songs: &[&Path]) -> Result<()> {
+    let mut dirs = BTreeMap::<&Path, Vec<&Path>>::new();
+    for song in songs {
+        if let (Some(dir), Some(name)) = (song.parent(), song.file_name()) {
+            dirs.entry(dir).or_default().push(Path::new(name));
+        }
+    }
+    for (dir, names) in dirs {
+        let sidecar = dir.join(SIDECAR);
+        let mut listed = match fs::read_to_string(&sidecar) {
+            // an empty sidecar already marks everything
+            Ok(listed) if listed.trim().is_empty() => continue,
+            Ok(listed) => listed,
+            Err(_) => String::new(),
+        };
+        if !listed.is_empty() && !listed.ends_with('\n') {
+            listed.push('\n');
+        }
+        for name in names {
+            listed.push_str(&name.to_string_lossy());
+            listed.push('\n');
+        }
+        fs::write(sidecar, listed)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers() {
+        let comment = "comment:keep me".parse::<Marker>().unwrap();
+        assert_eq!(comment.to_string(), "comment:keep me");
+        assert_eq!(
+            "field:UNIQUE".parse::<Marker>().unwrap().to_string(),
+            "field:UNIQUE"
+        );
+        assert_eq!(
+            "comment:".parse::<Marker>().unwrap().to_string(),
+            "comment:"
+        );
+        for invalid in ["field:", "unique", "tag:unique", ""] {
+            assert!(invalid.parse::<Marker>().is_err(), "{invalid}");
+        }
+
+        let mut tags = Tags {
+            comment: Some("keep me".to_string()),
+            ..Tags::default()
+        };
+        assert!(comment.is_set(&tags));
+        assert!(!Marker::default().is_set(&tags));
+        let field = Marker::Field("unique".to_string());
+        for (value, set) in [
+            ("1", true),
+            ("yes", true),
+            ("0", false),
+            ("False", false),
+            ("", false),
+        ] {
+            tags.fields.insert("UNIQUE".to_string(), value.to_string());
+            assert_eq!(field.is_set(&tags), set, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn sidecars() {
+        let root =
+            std::env::temp_dir().join(format!("subset-album-{}-sidecar", std::process::id()));
+        let album = root.join("Artist").join("Album");
+        let disc = album.join("CD1");
+        fs::create_dir_all(&disc).unwrap();
+        fs::write(root.join(SIDECAR), "").unwrap();
+        mark(&[&album.join("Intro.mp3"), &disc.join("Outro.mp3")]).unwrap();
+        fs::write(album.join(SIDECAR), "Intro.mp3\n\n  CD1/Song.mp3 \n").unwrap();
+
+        let sidecars = Sidecars::default();
+        let roots = [root.join("Artist")];
+        assert!(sidecars.marks(&album.join("Intro.mp3"), &roots));
+        assert!(sidecars.marks(&disc.join("Song.mp3"), &roots));
+        assert!(sidecars.marks(&disc.join("Outro.mp3"), &roots));
+        assert!(!sidecars.marks(&disc.join("Intro.mp3"), &roots));
+        // the empty sidecar above the root doesn't count
+        assert!(!sidecars.marks(&album.join("Other.mp3"), &roots));
+        assert!(sidecars.marks(&album.join("Other.mp3"), std::slice::from_ref(&root)));
+
+        // each sidecar is read once per scan
+        mark_folder(&album).unwrap();
+        assert!(!sidecars.marks(&album.join("Other.mp3"), &roots));
+        assert!(Sidecars::default().marks(&album.join("Other.mp3"), &roots));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
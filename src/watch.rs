@@ -0,0 +1,67 @@
+use crate::{config::GroupBy, messages::*};
+use anyhow::Result;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+/// How long the collection has to be quiet before changed artists are rescanned,
+/// so copying an album doesn't trigger a rescan per file.
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// Watches `root` and rescans every artist folder files were added to, removed
+/// from or changed in. Unless grouping by folders the whole collection is rescanned instead.
+pub fn spawn(root: PathBuf, group_by: GroupBy, work_sender: Sender<WorkMessage>) -> Result<()> {
+    let (event_sender, events) = channel::<notify::Result<notify::Event>>();
+    let mut watcher = notify::recommended_watcher(event_sender)?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+    thread::spawn(move || {
+        // keep the watcher alive as long as we are listening to it
+        let _watcher = watcher;
+        let mut changed = BTreeSet::new();
+        let mut everything = false;
+        loop {
+            match events.recv_timeout(SETTLE) {
+                Ok(Ok(event)) => {
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                    ) {
+                        match group_by {
+                            GroupBy::Folders => changed
+                                .extend(event.paths.iter().filter_map(|p| artist_dir(&root, p))),
+                            GroupBy::Tags | GroupBy::Layout => everything = true,
+                        }
+                    }
+                }
+                Ok(Err(e)) => eprintln!("Error watching collection: {e}"),
+                Err(RecvTimeoutError::Timeout) => {
+                    let mut messages = std::mem::take(&mut changed)
+                        .into_iter()
+                        .map(WorkMessage::WorkOnFolder)
+                        .collect::<Vec<_>>();
+                    if std::mem::take(&mut everything) {
+                        messages.push(WorkMessage::Rescan);
+                    }
+                    for message in messages {
+                        if work_sender.send(message).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    });
+    Ok(())
+}
+
+/// The top level folder of `root` that `path` lives in.
+fn artist_dir(root: &Path, path: &Path) -> Option<PathBuf> {
+    let artist = path.strip_prefix(root).ok()?.components().next()?;
+    Some(root.join(artist))
+}